@@ -0,0 +1,263 @@
+use std::error::Error;
+use std::io::{Read, Seek, Write};
+use std::sync::Arc;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::Float64Type;
+use arrow_array::{Array, ArrayRef, Float64Array, RecordBatch, StringArray, UInt32Array};
+use arrow_ipc::reader::FileReader;
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{DataType, Field, Schema};
+
+use crate::rows::{Column, InputError, InputRow, RowErrors};
+
+fn column_index(schema: &Schema, column: &Column) -> Result<usize, InputError> {
+    match column {
+        Column::Index(index) if *index < schema.fields().len() => Ok(*index),
+        Column::Index(index) => Err(InputError::UnknownColumn(index.to_string())),
+        Column::Name(name) => schema
+            .index_of(name)
+            .map_err(|_| InputError::UnknownColumn(name.clone())),
+    }
+}
+
+/// Rows of an Arrow IPC (Feather v2) file. The label column is read as text and the
+/// features are `features`, or every other numeric column when missing.
+/// Rows with a null or non-finite value, or a weight that is not positive, are invalid
+pub fn read_table(
+    reader: impl Read + Seek,
+    label: &Column,
+    features: Option<&[Column]>,
+    weight: Option<&Column>,
+    errors: &mut RowErrors,
+) -> Result<Vec<InputRow>, Box<dyn Error>> {
+    let reader = FileReader::try_new(reader, None)?;
+    let schema = reader.schema();
+    let label = column_index(&schema, label)?;
+    let weight = weight
+        .map(|column| column_index(&schema, column))
+        .transpose()?;
+    let features: Vec<usize> = match features {
+        Some(columns) => columns
+            .iter()
+            .map(|column| column_index(&schema, column))
+            .collect::<Result<_, _>>()?,
+        None => (0..schema.fields().len())
+            .filter(|&index| {
+                index != label
+                    && Some(index) != weight
+                    && schema.field(index).data_type().is_numeric()
+            })
+            .collect(),
+    };
+    if features.is_empty() {
+        return Err("the Arrow file has no numeric feature column".into());
+    }
+    if let Some(field) = features
+        .iter()
+        .chain(&weight)
+        .map(|&index| schema.field(index))
+        .find(|field| !field.data_type().is_numeric())
+    {
+        return Err(format!(
+            "column `{}` is {}, not numeric",
+            field.name(),
+            field.data_type()
+        )
+        .into());
+    }
+
+    let mut rows = Vec::new();
+    let mut line = 0;
+    for batch in reader {
+        let batch = batch?;
+        let labels = arrow_cast::cast(batch.column(label), &DataType::Utf8)?;
+        let labels = labels.as_string::<i32>();
+        // The weight comes last, after the features
+        let columns = features
+            .iter()
+            .chain(&weight)
+            .map(|&index| arrow_cast::cast(batch.column(index), &DataType::Float64))
+            .collect::<Result<Vec<ArrayRef>, _>>()?;
+        let columns: Vec<&Float64Array> = columns
+            .iter()
+            .map(|column| column.as_primitive::<Float64Type>())
+            .collect();
+
+        for row in 0..batch.num_rows() {
+            line += 1;
+            let content = match labels.is_null(row) {
+                true => String::new(),
+                false => labels.value(row).to_string(),
+            };
+            let invalid = |message: String| InputError::Parse {
+                line,
+                content: content.clone(),
+                message,
+            };
+            let null = std::iter::once((label, labels.is_null(row))).chain(
+                features
+                    .iter()
+                    .chain(&weight)
+                    .zip(&columns)
+                    .map(|(&index, column)| (index, column.is_null(row))),
+            );
+            let row = match null.into_iter().find(|&(_, is_null)| is_null) {
+                Some((index, _)) => Err(invalid(format!(
+                    "null in column `{}`",
+                    schema.field(index).name()
+                ))),
+                None => {
+                    let mut values: Vec<f64> =
+                        columns.iter().map(|column| column.value(row)).collect();
+                    let weight = match weight {
+                        Some(_) => values.pop().unwrap(),
+                        None => 1.0,
+                    };
+                    if !values.iter().all(|value| value.is_finite()) {
+                        Err(invalid("non-finite value".to_string()))
+                    } else if !(weight.is_finite() && weight > 0.0) {
+                        Err(invalid(format!("weight {weight} is not positive")))
+                    } else {
+                        Ok((content.clone(), None, values, weight))
+                    }
+                }
+            };
+            if let Some(row) = errors.handle(row)? {
+                rows.push(row);
+            }
+        }
+    }
+    if rows.is_empty() {
+        return Err(InputError::Empty.into());
+    }
+    Ok(rows)
+}
+
+/// Writes an Arrow IPC file with the label and the cluster of every entry,
+/// and their distance to the center when given
+pub fn write_assignments(
+    writer: impl Write,
+    label_name: &str,
+    labels: Vec<&str>,
+    clusters: Vec<u32>,
+    distances: Option<Vec<f64>>,
+) -> Result<(), Box<dyn Error>> {
+    let mut fields = vec![
+        Field::new(label_name, DataType::Utf8, false),
+        Field::new("cluster", DataType::UInt32, false),
+    ];
+    let mut columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from(labels)),
+        Arc::new(UInt32Array::from(clusters)),
+    ];
+    if let Some(distances) = distances {
+        fields.push(Field::new("distance", DataType::Float64, false));
+        columns.push(Arc::new(Float64Array::from(distances)));
+    }
+    let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)?;
+
+    let mut writer = FileWriter::try_new(writer, &batch.schema())?;
+    writer.write(&batch)?;
+    writer.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use arrow_array::{Float32Array, Int64Array};
+
+    use super::*;
+    use crate::rows::OnError;
+
+    /// Feather v2 file with a text label, two numeric columns and a text column
+    fn fixture() -> Vec<u8> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Utf8, true),
+            Field::new("x", DataType::Float32, true),
+            Field::new("y", DataType::Int64, true),
+            Field::new("note", DataType::Utf8, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec![Some("a"), Some("b"), None])),
+                Arc::new(Float32Array::from(vec![Some(1.5), None, Some(3.0)])),
+                Arc::new(Int64Array::from(vec![10, 20, 30])),
+                Arc::new(StringArray::from(vec!["p", "q", "r"])),
+            ],
+        )
+        .unwrap();
+        let mut bytes = Vec::new();
+        let mut writer = FileWriter::try_new(&mut bytes, &schema).unwrap();
+        writer.write(&batch).unwrap();
+        writer.write(&batch).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+        bytes
+    }
+
+    #[test]
+    fn test_read_table() {
+        let label = Column::Name("id".to_string());
+        let mut errors = RowErrors::new(OnError::Skip);
+        let rows = read_table(Cursor::new(fixture()), &label, None, None, &mut errors).unwrap();
+        assert_eq!(rows, vec![("a".to_string(), None, vec![1.5, 10.0], 1.0); 2]);
+        assert_eq!(errors.skipped(), 4);
+
+        let weight = Column::Name("y".to_string());
+        let rows = read_table(
+            Cursor::new(fixture()),
+            &label,
+            None,
+            Some(&weight),
+            &mut errors,
+        )
+        .unwrap();
+        assert_eq!(rows[0], ("a".to_string(), None, vec![1.5], 10.0));
+
+        let mut errors = RowErrors::new(OnError::Fail);
+        let error =
+            read_table(Cursor::new(fixture()), &label, None, None, &mut errors).unwrap_err();
+        assert_eq!(error.to_string(), "line 2: null in column `x`: `b`");
+
+        let features = [Column::Index(2), Column::Name("note".to_string())];
+        let error = read_table(
+            Cursor::new(fixture()),
+            &label,
+            Some(&features),
+            None,
+            &mut errors,
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "column `note` is Utf8, not numeric");
+    }
+
+    #[test]
+    fn test_write_assignments() {
+        let mut bytes = Vec::new();
+        write_assignments(
+            &mut bytes,
+            "id",
+            vec!["a", "b"],
+            vec![1, 0],
+            Some(vec![0.5, 0.0]),
+        )
+        .unwrap();
+
+        let reader = FileReader::try_new(Cursor::new(bytes), None).unwrap();
+        let batches: Vec<RecordBatch> = reader.map(Result::unwrap).collect();
+        let batch = &batches[0];
+        assert_eq!(batch.schema().field(0).name(), "id");
+        assert_eq!(
+            batch
+                .column(1)
+                .as_primitive::<arrow_array::types::UInt32Type>()
+                .values(),
+            &[1, 0]
+        );
+        assert_eq!(batch.num_columns(), 3);
+    }
+}
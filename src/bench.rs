@@ -0,0 +1,207 @@
+use std::error::Error;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use itertools::Itertools;
+
+use crate::entities::{Cluster, Point};
+use crate::input::Mode;
+use crate::kmeans::Kmeans;
+
+/// Layout of the `bench` report
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug, Hash)]
+pub enum ReportFormat {
+    /// Aligned columns, for reading
+    Table,
+
+    /// Comma separated values with a header
+    Csv,
+}
+
+/// Timings of one mode over every repetition
+#[derive(Debug, Clone, PartialEq)]
+pub struct Measurement {
+    pub mode: Mode,
+    pub runs: usize,
+    pub mean: Duration,
+    pub min: Duration,
+    pub iterations: usize,
+    pub matches_serial: bool,
+}
+
+/// Runs every runner `repetitions` times from the same initial centers.
+/// Clusters are compared, points included, with the ones of `Mode::S`, which must be among the runners
+pub fn run(
+    runners: impl IntoIterator<Item = (Mode, Box<dyn Kmeans>)>,
+    data: &[Point],
+    k: usize,
+    initial_centers: &[Point],
+    repetitions: usize,
+) -> Vec<Measurement> {
+    let repetitions = repetitions.max(1);
+    let mut serial: Option<Vec<Cluster>> = None;
+    let mut results = Vec::new();
+
+    for (mode, runner) in runners {
+        let mut times = Vec::with_capacity(repetitions);
+        let mut last = None;
+        for _ in 0..repetitions {
+            let start = Instant::now();
+            let execution = runner.run(data, k, initial_centers.to_vec());
+            times.push(start.elapsed());
+            last = Some(execution);
+        }
+        let execution = last.unwrap();
+
+        if mode == Mode::S {
+            serial = Some(execution.clusters.clone());
+        }
+        results.push((mode, times, execution));
+    }
+
+    let serial = serial.expect("the serial mode is the reference of the benchmark");
+    results
+        .into_iter()
+        .map(|(mode, times, execution)| Measurement {
+            mode,
+            runs: times.len(),
+            mean: times.iter().sum::<Duration>() / times.len() as u32,
+            min: times.iter().min().copied().unwrap_or_default(),
+            iterations: execution.iterations,
+            matches_serial: execution.clusters == serial,
+        })
+        .collect()
+}
+
+pub fn write_report(
+    mut writer: impl Write,
+    format: ReportFormat,
+    measurements: &[Measurement],
+) -> Result<(), Box<dyn Error>> {
+    let serial_mean = measurements
+        .iter()
+        .find(|measurement| measurement.mode == Mode::S)
+        .map(|measurement| measurement.mean);
+
+    let rows: Vec<[String; 7]> = measurements
+        .iter()
+        .map(|measurement| {
+            let speedup = serial_mean.map_or(f64::NAN, |serial| {
+                serial.as_secs_f64() / measurement.mean.as_secs_f64()
+            });
+            [
+                mode_name(measurement.mode),
+                measurement.runs.to_string(),
+                format!("{:.3}", measurement.mean.as_secs_f64() * 1000.0),
+                format!("{:.3}", measurement.min.as_secs_f64() * 1000.0),
+                measurement.iterations.to_string(),
+                format!("{speedup:.2}"),
+                measurement.matches_serial.to_string(),
+            ]
+        })
+        .collect();
+
+    match format {
+        ReportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(writer);
+            writer.write_record([
+                "mode",
+                "runs",
+                "mean_ms",
+                "min_ms",
+                "iterations",
+                "speedup",
+                "matches_serial",
+            ])?;
+            for row in rows {
+                writer.write_record(row)?;
+            }
+            writer.flush()?;
+        }
+        ReportFormat::Table => {
+            let header = [
+                "mode",
+                "runs",
+                "mean (ms)",
+                "min (ms)",
+                "iterations",
+                "speedup",
+                "matches serial",
+            ]
+            .map(String::from);
+            let mut widths = header.each_ref().map(String::len);
+            for row in &rows {
+                for (width, cell) in widths.iter_mut().zip(row) {
+                    *width = (*width).max(cell.len());
+                }
+            }
+            for row in [header].iter().chain(&rows) {
+                let line = row
+                    .iter()
+                    .zip(widths)
+                    .enumerate()
+                    .map(|(i, (cell, width))| match i {
+                        0 => format!("{cell:<width$}"),
+                        _ => format!("{cell:>width$}"),
+                    })
+                    .join("  ");
+                writeln!(writer, "{line}")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn mode_name(mode: Mode) -> String {
+    mode.to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_else(|| format!("{mode:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kmeans::parallel_2::KmeansParallelBuilder2;
+    use crate::kmeans::serial::KmeansSerialBuilder;
+
+    #[test]
+    fn test_matches_serial_compares_assignments() {
+        let data: Vec<Point> = (0..40).map(|n| Point::from([n % 7, n % 3])).collect();
+        let initial_centers = vec![Point::from([0, 0]), Point::from([6, 2])];
+        let runners: Vec<(Mode, Box<dyn Kmeans>)> = vec![
+            (Mode::S, Box::new(KmeansSerialBuilder)),
+            (Mode::Ray, Box::new(KmeansParallelBuilder2::new(2))),
+        ];
+
+        let measurements = run(runners, &data, 2, &initial_centers, 1);
+        assert!(measurements
+            .iter()
+            .all(|measurement| measurement.matches_serial));
+    }
+
+    #[test]
+    fn test_csv_report() {
+        let measurement = |mode, millis| Measurement {
+            mode,
+            runs: 2,
+            mean: Duration::from_millis(millis),
+            min: Duration::from_millis(millis),
+            iterations: 3,
+            matches_serial: true,
+        };
+        let mut bytes = Vec::new();
+        write_report(
+            &mut bytes,
+            ReportFormat::Csv,
+            &[measurement(Mode::S, 10), measurement(Mode::Ray, 4)],
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            "mode,runs,mean_ms,min_ms,iterations,speedup,matches_serial\n\
+             s,2,10.000,10.000,3,1.00,true\n\
+             ray,2,4.000,4.000,3,2.50,true\n"
+        );
+    }
+}
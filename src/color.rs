@@ -0,0 +1,275 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// Color space the points are clustered in. Input and output are always RGB
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorSpace {
+    /// Raw RGB values (0-255)
+    #[default]
+    Rgb,
+
+    /// CIE L*a*b* (D65 white point)
+    Lab,
+
+    /// CIE L*u*v* (D65 white point)
+    Luv,
+
+    /// HSV with the hue as an angle: saturation times the cosine and sine of the hue,
+    /// then value, all in percent. Hues on both sides of 0° stay close
+    Hsv,
+
+    /// Full range BT.601 YCbCr, as used by JPEG
+    Ycbcr,
+}
+
+// D65 reference white
+const WHITE: [f64; 3] = [0.95047, 1.0, 1.08883];
+
+const EPSILON: f64 = 216.0 / 24389.0;
+const KAPPA: f64 = 24389.0 / 27.0;
+
+impl ColorSpace {
+    pub fn convert_rgb(self, rgb: [f64; 3]) -> [f64; 3] {
+        match self {
+            ColorSpace::Rgb => rgb,
+            ColorSpace::Lab => xyz_to_lab(rgb_to_xyz(rgb)),
+            ColorSpace::Luv => xyz_to_luv(rgb_to_xyz(rgb)),
+            ColorSpace::Hsv => rgb_to_hsv(rgb),
+            ColorSpace::Ycbcr => rgb_to_ycbcr(rgb),
+        }
+    }
+
+    pub fn to_rgb(self, values: [f64; 3]) -> [f64; 3] {
+        match self {
+            ColorSpace::Rgb => values,
+            ColorSpace::Lab => xyz_to_rgb(lab_to_xyz(values)),
+            ColorSpace::Luv => xyz_to_rgb(luv_to_xyz(values)),
+            ColorSpace::Hsv => hsv_to_rgb(values),
+            ColorSpace::Ycbcr => ycbcr_to_rgb(values),
+        }
+    }
+
+    /// RGB color of the first three `values`, rounded to bytes
+    pub fn to_rgb8(self, values: &[f64]) -> [u8; 3] {
+        self.to_rgb([values[0], values[1], values[2]])
+            .map(|n| n.round().clamp(0.0, 255.0) as u8)
+    }
+}
+
+fn srgb_to_linear(c: f64) -> f64 {
+    let c = c / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f64) -> f64 {
+    let c = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.max(0.0).powf(1.0 / 2.4) - 0.055
+    };
+    c * 255.0
+}
+
+fn rgb_to_xyz(rgb: [f64; 3]) -> [f64; 3] {
+    let [r, g, b] = rgb.map(srgb_to_linear);
+    [
+        0.4124564 * r + 0.3575761 * g + 0.1804375 * b,
+        0.2126729 * r + 0.7151522 * g + 0.0721750 * b,
+        0.0193339 * r + 0.1191920 * g + 0.9503041 * b,
+    ]
+}
+
+fn xyz_to_rgb([x, y, z]: [f64; 3]) -> [f64; 3] {
+    [
+        3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+        -0.9692660 * x + 1.8760108 * y + 0.0415560 * z,
+        0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+    ]
+    .map(linear_to_srgb)
+}
+
+fn xyz_to_lab([x, y, z]: [f64; 3]) -> [f64; 3] {
+    let f = |t: f64| {
+        if t > EPSILON {
+            t.cbrt()
+        } else {
+            (KAPPA * t + 16.0) / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x / WHITE[0]), f(y / WHITE[1]), f(z / WHITE[2]));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+fn lab_to_xyz([l, a, b]: [f64; 3]) -> [f64; 3] {
+    let fy = (l + 16.0) / 116.0;
+    let fx = fy + a / 500.0;
+    let fz = fy - b / 200.0;
+    let f_inv = |t: f64| {
+        if t.powi(3) > EPSILON {
+            t.powi(3)
+        } else {
+            (116.0 * t - 16.0) / KAPPA
+        }
+    };
+    let y = if l > KAPPA * EPSILON {
+        fy.powi(3)
+    } else {
+        l / KAPPA
+    };
+    [f_inv(fx) * WHITE[0], y * WHITE[1], f_inv(fz) * WHITE[2]]
+}
+
+fn uv_prime([x, y, z]: [f64; 3]) -> (f64, f64) {
+    let denominator = x + 15.0 * y + 3.0 * z;
+    if denominator == 0.0 {
+        return (0.0, 0.0);
+    }
+    (4.0 * x / denominator, 9.0 * y / denominator)
+}
+
+fn xyz_to_luv(xyz: [f64; 3]) -> [f64; 3] {
+    let yr = xyz[1] / WHITE[1];
+    let l = if yr > EPSILON {
+        116.0 * yr.cbrt() - 16.0
+    } else {
+        KAPPA * yr
+    };
+    let (u, v) = uv_prime(xyz);
+    let (un, vn) = uv_prime(WHITE);
+    [l, 13.0 * l * (u - un), 13.0 * l * (v - vn)]
+}
+
+fn luv_to_xyz([l, u, v]: [f64; 3]) -> [f64; 3] {
+    if l <= 0.0 {
+        return [0.0; 3];
+    }
+    let (un, vn) = uv_prime(WHITE);
+    let u_prime = u / (13.0 * l) + un;
+    let v_prime = v / (13.0 * l) + vn;
+    let y = if l > KAPPA * EPSILON {
+        ((l + 16.0) / 116.0).powi(3)
+    } else {
+        l / KAPPA
+    } * WHITE[1];
+    let x = y * 9.0 * u_prime / (4.0 * v_prime);
+    let z = y * (12.0 - 3.0 * u_prime - 20.0 * v_prime) / (4.0 * v_prime);
+    [x, y, z]
+}
+
+fn rgb_to_hsv(rgb: [f64; 3]) -> [f64; 3] {
+    let [r, g, b] = rgb.map(|c| c / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    let saturation = if max == 0.0 { 0.0 } else { delta / max } * 100.0;
+    let (sin, cos) = hue.to_radians().sin_cos();
+
+    [saturation * cos, saturation * sin, max * 100.0]
+}
+
+fn hsv_to_rgb([hue_x, hue_y, v]: [f64; 3]) -> [f64; 3] {
+    let (s, v) = (hue_x.hypot(hue_y).min(100.0) / 100.0, v / 100.0);
+    let c = v * s;
+    let h = hue_y.atan2(hue_x).to_degrees().rem_euclid(360.0) / 60.0;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = v - c;
+    [r, g, b].map(|n| (n + m) * 255.0)
+}
+
+fn rgb_to_ycbcr([r, g, b]: [f64; 3]) -> [f64; 3] {
+    [
+        0.299 * r + 0.587 * g + 0.114 * b,
+        128.0 - 0.168736 * r - 0.331264 * g + 0.5 * b,
+        128.0 + 0.5 * r - 0.418688 * g - 0.081312 * b,
+    ]
+}
+
+fn ycbcr_to_rgb([y, cb, cr]: [f64; 3]) -> [f64; 3] {
+    let (cb, cr) = (cb - 128.0, cr - 128.0);
+    [
+        y + 1.402 * cr,
+        y - 0.344136 * cb - 0.714136 * cr,
+        y + 1.772 * cb,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::Point;
+    use crate::kmeans::serial::KmeansSerialBuilder;
+    use crate::kmeans::Kmeans;
+
+    const SAMPLES: [[f64; 3]; 6] = [
+        [0.0, 0.0, 0.0],
+        [255.0, 255.0, 255.0],
+        [255.0, 0.0, 0.0],
+        [12.0, 200.0, 99.0],
+        [30.0, 30.0, 240.0],
+        [128.0, 64.0, 200.0],
+    ];
+
+    #[test]
+    fn test_round_trip_every_color_space() {
+        for space in ColorSpace::value_variants() {
+            for rgb in SAMPLES {
+                let back = space.to_rgb(space.convert_rgb(rgb));
+                for (a, b) in rgb.iter().zip(back) {
+                    assert!((a - b).abs() < 1e-3, "{space:?}: {rgb:?} -> {back:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_lab_reference_values() {
+        let [l, a, b] = ColorSpace::Lab.convert_rgb([255.0, 0.0, 0.0]);
+        assert!((l - 53.24).abs() < 0.01);
+        assert!((a - 80.09).abs() < 0.01);
+        assert!((b - 67.20).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_hsv_clusters_reds_across_0_degrees() {
+        // Hues of about 350° and 10°, and two greens
+        let rgbs = [
+            [255.0, 0.0, 43.0],
+            [255.0, 43.0, 0.0],
+            [0.0, 255.0, 0.0],
+            [20.0, 230.0, 20.0],
+        ];
+        let data: Vec<Point> = rgbs
+            .iter()
+            .map(|&rgb| Point::from(ColorSpace::Hsv.convert_rgb(rgb)))
+            .collect();
+        let initial_centers = vec![data[0].clone(), data[2].clone()];
+
+        let clusters = KmeansSerialBuilder.execute(&data, 2, initial_centers);
+        assert_eq!(clusters[0].points.len(), 2);
+        let [r, g, b] = ColorSpace::Hsv.to_rgb8(clusters[0].center.get_data());
+        assert!(r > 250 && g < 30 && b < 30, "{:?}", [r, g, b]);
+    }
+}
@@ -1,24 +1,29 @@
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone)]
 pub struct Point {
-    x: u32,
-    y: u32,
-    z: u32,
+    values: Vec<f64>,
     label: Option<Arc<str>>,
+    /// How many entries the point stands for, 1 unless the input says otherwise
+    weight: f64,
 }
 
 impl Point {
+    #[allow(dead_code)]
     pub fn from<T, const N: usize>(arr: [T; N]) -> Self
     where
-        T: Into<u32>,
+        T: Into<f64>,
     {
-        let arr: [u32; N] = arr.map(|n| n.into());
+        Self::from_vec(arr.map(|n| n.into()).to_vec())
+    }
+
+    pub fn from_vec(values: Vec<f64>) -> Self {
         Self {
-            x: *arr.get(0).unwrap_or(&0),
-            y: *arr.get(1).unwrap_or(&0),
-            z: *arr.get(2).unwrap_or(&0),
+            values,
             label: None,
+            weight: 1.0,
         }
     }
 
@@ -27,23 +32,84 @@ impl Point {
         self
     }
 
+    pub fn with_weight(mut self, weight: f64) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    /// Same point (and label) with its coordinates transformed by `f`
+    pub fn map_data(&self, f: impl FnOnce(&[f64]) -> Vec<f64>) -> Self {
+        Self {
+            values: f(&self.values),
+            label: self.label.clone(),
+            weight: self.weight,
+        }
+    }
+
     pub fn get_label(&self) -> Option<&str> {
         self.label.as_deref()
     }
 
-    pub fn get_data(&self) -> [u32; 3] {
-        [self.x, self.y, self.z]
+    pub fn get_weight(&self) -> f64 {
+        self.weight
+    }
+
+    pub fn get_data(&self) -> &[f64] {
+        &self.values
+    }
+
+    /// Number of coordinates
+    pub fn dimension(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Squared euclidean distance
+    pub fn euclidean_distance(&self, other: &Point) -> f64 {
+        self.values
+            .iter()
+            .zip(&other.values)
+            .map(|(a, b)| (a - b).powi(2))
+            .sum()
+    }
+
+    /// Bit representation of the coordinates, usable as a hash key
+    pub fn get_values(&self) -> Vec<u64> {
+        self.values.iter().map(|n| n.to_bits()).collect()
+    }
+}
+
+impl PartialEq for Point {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Point {}
+
+impl PartialOrd for Point {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
+}
 
-    pub fn euclidean_distance(&self, other: &Point) -> u32 {
-        let x_diff = self.x - other.x;
-        let y_diff = self.y - other.y;
-        let z_diff = self.z - other.z;
-        x_diff.pow(2) + y_diff.pow(2) + z_diff.pow(2)
+impl Ord for Point {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.values
+            .iter()
+            .zip(&other.values)
+            .map(|(a, b)| a.total_cmp(b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| self.dimension().cmp(&other.dimension()))
+            .then_with(|| self.label.cmp(&other.label))
+            .then_with(|| self.weight.total_cmp(&other.weight))
     }
+}
 
-    pub fn get_values(&self) -> [u32; 3] {
-        [self.x, self.y, self.z]
+impl Hash for Point {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.get_values().hash(state);
+        self.label.hash(state);
+        self.weight.to_bits().hash(state);
     }
 }
 
@@ -61,16 +127,20 @@ impl Cluster<'_> {
         }
     }
 
+    /// Weighted mean of the points, the origin when there are none
     pub fn calculate_center_point(&self) -> Point {
-        let x_sum: u32 = self.points.iter().map(|point| point.x).sum();
-        let y_sum: u32 = self.points.iter().map(|point| point.y).sum();
-        let z_sum: u32 = self.points.iter().map(|point| point.z).sum();
-        let len = self.points.len() as u32;
-        Point {
-            label: None,
-            x: x_sum.checked_div(len).unwrap_or(0),
-            y: y_sum.checked_div(len).unwrap_or(0),
-            z: z_sum.checked_div(len).unwrap_or(0),
+        let mut sums = vec![0.0; self.center.dimension()];
+        let mut total_weight = 0.0;
+        for point in &self.points {
+            let weight = point.get_weight();
+            for (sum, value) in sums.iter_mut().zip(point.get_data()) {
+                *sum += weight * value;
+            }
+            total_weight += weight;
+        }
+        if total_weight > 0.0 {
+            sums.iter_mut().for_each(|sum| *sum /= total_weight);
         }
+        Point::from_vec(sums)
     }
 }
@@ -0,0 +1,90 @@
+use std::path::Path;
+
+use clap::ValueEnum;
+
+/// File format of the input or output
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug, Hash)]
+pub enum Format {
+    /// `X:Y R G B` lines, as produced by img-to-csv
+    Csv,
+
+    /// Netpbm image (PBM, PGM or PPM). Written as binary PPM
+    Pnm,
+
+    /// Netpbm image written as binary grayscale PGM
+    Pgm,
+
+    /// PNG image
+    Png,
+
+    /// One `{"label": ..., "values": [...]}` object per line
+    Ndjson,
+
+    /// One JSON document with the centers, the assignments and run statistics. Output only
+    Json,
+
+    /// NumPy array of float32, float64 or uint8 rows. Written as the int64 cluster of every entry
+    Npy,
+
+    /// ANN-benchmark float32 vectors. Input only
+    Fvecs,
+
+    /// ANN-benchmark uint8 vectors. Input only
+    Bvecs,
+
+    /// Arrow IPC file (Feather v2) of numeric columns. Written as the label and cluster columns
+    Arrow,
+}
+
+impl Format {
+    /// Format inferred from the file extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("csv" | "txt") => Some(Format::Csv),
+            Some("ppm" | "pnm" | "pbm") => Some(Format::Pnm),
+            Some("pgm") => Some(Format::Pgm),
+            Some("png") => Some(Format::Png),
+            Some("ndjson" | "jsonl") => Some(Format::Ndjson),
+            Some("json") => Some(Format::Json),
+            Some("npy") => Some(Format::Npy),
+            Some("fvecs") => Some(Format::Fvecs),
+            Some("bvecs") => Some(Format::Bvecs),
+            Some("arrow" | "feather" | "ipc") => Some(Format::Arrow),
+            _ => None,
+        }
+    }
+
+    pub fn is_image(self) -> bool {
+        matches!(self, Format::Pnm | Format::Pgm | Format::Png)
+    }
+
+    pub fn is_json(self) -> bool {
+        matches!(self, Format::Ndjson | Format::Json)
+    }
+
+    /// Binary arrays of numbers, without labels
+    pub fn is_array(self) -> bool {
+        matches!(self, Format::Npy | Format::Fvecs | Format::Bvecs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_from_extension() {
+        assert_eq!(Format::from_path(Path::new("a.PNG")), Some(Format::Png));
+        assert_eq!(Format::from_path(Path::new("a.ppm")), Some(Format::Pnm));
+        assert_eq!(Format::from_path(Path::new("a.csv")), Some(Format::Csv));
+        assert_eq!(
+            Format::from_path(Path::new("a.jsonl")),
+            Some(Format::Ndjson)
+        );
+        assert_eq!(Format::from_path(Path::new("a")), None);
+    }
+}
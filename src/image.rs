@@ -0,0 +1,289 @@
+use std::error::Error;
+use std::io::{BufRead, BufReader, Read, Write};
+
+use crate::format::Format;
+
+/// RGB pixels in row-major order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[u8; 3]>,
+}
+
+/// Most pixels an image read or drawn can have, 3 GiB of RGB values
+pub const MAX_PIXELS: usize = 1 << 30;
+
+/// Number of pixels of a `width` x `height` image, when it is not larger than [`MAX_PIXELS`]
+fn pixel_count(width: usize, height: usize) -> Result<usize, String> {
+    width
+        .checked_mul(height)
+        .filter(|&len| len <= MAX_PIXELS)
+        .ok_or_else(|| format!("a {width}x{height} image is too large"))
+}
+
+impl Image {
+    /// A black image, or an error when it would be larger than [`MAX_PIXELS`]
+    pub fn new(width: usize, height: usize) -> Result<Self, String> {
+        Ok(Self {
+            width,
+            height,
+            pixels: vec![[0; 3]; pixel_count(width, height)?],
+        })
+    }
+
+    pub fn read(reader: impl Read, format: Format) -> Result<Self, Box<dyn Error>> {
+        match format {
+            Format::Pnm | Format::Pgm => read_pnm(BufReader::new(reader)),
+            Format::Png => read_png(reader),
+            Format::Csv
+            | Format::Ndjson
+            | Format::Json
+            | Format::Npy
+            | Format::Fvecs
+            | Format::Bvecs
+            | Format::Arrow => Err(format!("{format:?} is not an image format").into()),
+        }
+    }
+
+    pub fn write(&self, writer: impl Write, format: Format) -> Result<(), Box<dyn Error>> {
+        match format {
+            Format::Pnm => write_pnm(self, writer, false),
+            Format::Pgm => write_pnm(self, writer, true),
+            Format::Png => write_png(self, writer),
+            Format::Csv
+            | Format::Ndjson
+            | Format::Json
+            | Format::Npy
+            | Format::Fvecs
+            | Format::Bvecs
+            | Format::Arrow => Err(format!("{format:?} is not an image format").into()),
+        }
+    }
+
+    /// `X:Y` position and color of every pixel
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize, [u8; 3])> + '_ {
+        self.pixels
+            .iter()
+            .enumerate()
+            .map(|(i, &rgb)| (i % self.width, i / self.width, rgb))
+    }
+}
+
+fn read_png(reader: impl Read) -> Result<Image, Box<dyn Error>> {
+    let mut decoder = png::Decoder::new(reader);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    let buffer = &buffer[..info.buffer_size()];
+
+    let pixels = match info.color_type {
+        png::ColorType::Grayscale => buffer.iter().map(|&v| [v; 3]).collect(),
+        png::ColorType::GrayscaleAlpha => buffer.chunks_exact(2).map(|c| [c[0]; 3]).collect(),
+        png::ColorType::Rgb => buffer.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect(),
+        png::ColorType::Rgba => buffer.chunks_exact(4).map(|c| [c[0], c[1], c[2]]).collect(),
+        png::ColorType::Indexed => return Err("unexpanded indexed PNG".into()),
+    };
+
+    Ok(Image {
+        width: info.width as usize,
+        height: info.height as usize,
+        pixels,
+    })
+}
+
+fn write_png(image: &Image, writer: impl Write) -> Result<(), Box<dyn Error>> {
+    let mut encoder = png::Encoder::new(writer, image.width as u32, image.height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&image.pixels.concat())?;
+    Ok(())
+}
+
+/// Next whitespace separated token of a Netpbm header, skipping `#` comments
+fn pnm_token(reader: &mut impl BufRead) -> Result<String, Box<dyn Error>> {
+    let mut token = String::new();
+    let mut byte = [0];
+    loop {
+        if reader.read(&mut byte)? == 0 {
+            break;
+        }
+        match byte[0] {
+            b'#' => {
+                reader.read_until(b'\n', &mut Vec::new())?;
+            }
+            b if b.is_ascii_whitespace() => {
+                if !token.is_empty() {
+                    break;
+                }
+            }
+            b => token.push(b as char),
+        }
+    }
+    if token.is_empty() {
+        return Err("unexpected end of Netpbm header".into());
+    }
+    Ok(token)
+}
+
+fn pnm_number(reader: &mut impl BufRead) -> Result<usize, Box<dyn Error>> {
+    let token = pnm_token(reader)?;
+    Ok(token
+        .parse()
+        .map_err(|_| format!("invalid number `{token}` in Netpbm file"))?)
+}
+
+fn read_pnm(mut reader: impl BufRead) -> Result<Image, Box<dyn Error>> {
+    let magic = pnm_token(&mut reader)?;
+    let width = pnm_number(&mut reader)?;
+    let height = pnm_number(&mut reader)?;
+    let max_value = match magic.as_str() {
+        "P1" | "P4" => 1,
+        _ => pnm_number(&mut reader)?.max(1),
+    };
+    if max_value > u16::MAX as usize {
+        return Err(format!("Netpbm maximum value {max_value} is larger than 65535").into());
+    }
+    let len = pixel_count(width, height)?;
+    let scale = |v: usize| (v.min(max_value) * 255 / max_value) as u8;
+
+    let samples: Vec<usize> = match magic.as_str() {
+        "P1" => {
+            // Digits of plain PBM don't need to be separated by whitespace
+            let mut text = String::new();
+            reader.read_to_string(&mut text)?;
+            text.lines()
+                .flat_map(|line| line.split('#').next())
+                .flat_map(|line| line.bytes())
+                .filter(|b| matches!(b, b'0' | b'1'))
+                .map(|b| usize::from(b == b'0'))
+                .collect()
+        }
+        "P2" | "P3" => {
+            let mut text = String::new();
+            reader.read_to_string(&mut text)?;
+            text.lines()
+                .flat_map(|line| line.split('#').next())
+                .flat_map(str::split_whitespace)
+                .map(|token| {
+                    token
+                        .parse()
+                        .map_err(|_| format!("invalid sample `{token}` in Netpbm file"))
+                })
+                .collect::<Result<_, _>>()?
+        }
+        "P4" => {
+            let row_bytes = width.div_ceil(8);
+            // Up to what the header announces, the rest is checked against `len` below
+            let mut bytes = Vec::new();
+            reader
+                .take((row_bytes * height) as u64)
+                .read_to_end(&mut bytes)?;
+            bytes
+                .chunks_exact(row_bytes.max(1))
+                .flat_map(|row| (0..width).map(move |x| (row[x / 8] >> (7 - x % 8)) & 1))
+                .map(|bit| usize::from(bit == 0))
+                .collect()
+        }
+        "P5" | "P6" => {
+            let channels = if magic == "P5" { 1 } else { 3 };
+            let sample_size = if max_value > 255 { 2 } else { 1 };
+            let mut bytes = Vec::new();
+            reader
+                .take((len * channels * sample_size) as u64)
+                .read_to_end(&mut bytes)?;
+            bytes
+                .chunks_exact(sample_size)
+                .map(|c| c.iter().fold(0, |acc, &b| acc << 8 | b as usize))
+                .collect()
+        }
+        _ => return Err(format!("unsupported Netpbm magic number `{magic}`").into()),
+    };
+
+    let pixels: Vec<[u8; 3]> = match magic.as_str() {
+        "P3" | "P6" => samples
+            .chunks_exact(3)
+            .map(|c| [scale(c[0]), scale(c[1]), scale(c[2])])
+            .collect(),
+        _ => samples.iter().map(|&v| [scale(v); 3]).collect(),
+    };
+    if pixels.len() < len {
+        return Err(format!(
+            "expected {len} pixels in Netpbm file, found {}",
+            pixels.len()
+        )
+        .into());
+    }
+
+    Ok(Image {
+        width,
+        height,
+        pixels: pixels[..len].to_vec(),
+    })
+}
+
+fn write_pnm(image: &Image, mut writer: impl Write, grayscale: bool) -> Result<(), Box<dyn Error>> {
+    if grayscale {
+        writeln!(writer, "P5\n{} {}\n255", image.width, image.height)?;
+        let luma: Vec<u8> = image
+            .pixels
+            .iter()
+            .map(|&[r, g, b]| {
+                (0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64).round() as u8
+            })
+            .collect();
+        writer.write_all(&luma)?;
+    } else {
+        writeln!(writer, "P6\n{} {}\n255", image.width, image.height)?;
+        writer.write_all(&image.pixels.concat())?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Image {
+        Image {
+            width: 3,
+            height: 2,
+            pixels: vec![
+                [255, 0, 0],
+                [0, 255, 0],
+                [0, 0, 255],
+                [10, 20, 30],
+                [0, 0, 0],
+                [255, 255, 255],
+            ],
+        }
+    }
+
+    #[test]
+    fn test_round_trip_png_and_ppm() {
+        for format in [Format::Png, Format::Pnm] {
+            let mut bytes = Vec::new();
+            sample().write(&mut bytes, format).unwrap();
+            assert_eq!(Image::read(bytes.as_slice(), format).unwrap(), sample());
+        }
+    }
+
+    #[test]
+    fn test_read_plain_pgm_with_comments() {
+        let text = "P2\n# a comment\n2 2\n15\n0 15\n# another\n5 10\n";
+        let image = Image::read(text.as_bytes(), Format::Pnm).unwrap();
+        assert_eq!(image.pixels, vec![[0; 3], [255; 3], [85; 3], [170; 3]]);
+    }
+
+    #[test]
+    fn test_too_large_images() {
+        let header_only = b"P6\n100000 100000\n255\nabc";
+        assert!(Image::read(&header_only[..], Format::Pnm).is_err());
+        let truncated = b"P5\n2 2\n255\nabc";
+        assert!(Image::read(&truncated[..], Format::Pnm).is_err());
+        assert!(Image::new(4_000_000_001, 4_000_000_001).is_err());
+    }
+}
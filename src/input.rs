@@ -1,28 +1,383 @@
-use clap::{Parser, ValueEnum};
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
 
+use crate::bench::ReportFormat;
+use crate::color::ColorSpace;
+use crate::format::Format;
+use crate::model::Preprocessing;
+use crate::rows::{Column, Layout, OnError};
+use crate::slic::SlicOutput;
+
 #[derive(Parser)]
 #[command(name = "kmeans", author, version, about, long_about = None)] // Get info in Cargo.toml
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Args {
-    #[arg(short, long, default_value = "2")]
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    // Without subcommand, the input is clustered like `fit` but no model is saved.
+    // Not an `Option`: clap can't tell whether args with flattened parts were given
+    #[command(flatten)]
+    pub cluster: ClusterArgs,
+
+    #[command(flatten)]
+    pub io: IoArgs,
+
+    /// Log progress to STDERR: `-v` summarizes every iteration, `-vv` adds the centers
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Cluster the input and save the fitted model
+    Fit(FitArgs),
+
+    /// Assign the input to the centers of a saved model, without refitting
+    Predict(PredictArgs),
+
+    /// Run every mode on the same input and initial centers and compare them
+    Bench(BenchArgs),
+
+    /// Cluster a CSV file larger than memory with repeated chunked passes over it
+    Stream(StreamArgs),
+
+    /// Update centers one point at a time from an endless `X:Y R G B` stream on STDIN
+    Online(OnlineArgs),
+}
+
+#[derive(clap::Args)]
+pub struct FitArgs {
+    /// File the model is saved to, as JSON
+    #[arg(long)]
+    pub model_out: PathBuf,
+
+    #[command(flatten)]
+    pub cluster: ClusterArgs,
+
+    #[command(flatten)]
+    pub io: IoArgs,
+}
+
+#[derive(clap::Args)]
+pub struct PredictArgs {
+    /// Model saved by `fit`
+    #[arg(long)]
+    pub model: PathBuf,
+
+    #[command(flatten)]
+    pub io: IoArgs,
+}
+
+#[derive(clap::Args)]
+pub struct BenchArgs {
+    /// Runs of every mode, the reported wall time is their mean
+    #[arg(short = 'n', long, default_value = "5")]
+    pub repetitions: usize,
+
+    #[command(flatten)]
+    pub init: InitArgs,
+
+    #[command(flatten)]
+    pub preprocessing: PreprocessingArgs,
+
+    /// Worker threads of the parallel modes: std threads, tokio workers or the rayon pool.
+    /// Defaults to the available parallelism
+    #[arg(short, long)]
+    pub threads: Option<NonZeroUsize>,
+
+    /// Layout of the report
+    #[arg(long, default_value = "table")]
+    pub report: ReportFormat,
+
+    /// Write the report to this file instead of STDOUT
+    #[arg(short, long)]
+    pub output_file: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub input: InputArgs,
+}
+
+#[derive(clap::Args)]
+pub struct StreamArgs {
+    /// Entries read and assigned at a time, bounding the memory used
+    #[arg(long, default_value = "1000000")]
+    pub chunk_size: usize,
+
+    #[command(flatten)]
+    pub init: InitArgs,
+
+    #[command(flatten)]
+    pub preprocessing: PreprocessingArgs,
+
+    #[command(flatten)]
+    pub io: IoArgs,
+}
+
+#[derive(clap::Args)]
+pub struct OnlineArgs {
+    #[arg(short, long, default_value = "2", value_parser = parse_k)]
     pub k: usize,
 
+    /// Fixed step of a center towards each of its points. By default a center
+    /// is the running mean of its points
+    #[arg(short, long)]
+    pub learning_rate: Option<f64>,
+
+    /// Write the centers after every N points
+    #[arg(short, long)]
+    pub every: Option<usize>,
+
+    /// Write the centers every this many milliseconds, when points arrived in between
+    #[arg(short, long)]
+    pub interval_ms: Option<u64>,
+
+    #[command(flatten)]
+    pub preprocessing: PreprocessingArgs,
+
+    /// Write the centers to this file instead of STDOUT
     #[arg(short, long)]
     pub output_file: Option<PathBuf>,
 
+    /// What to do with rows that can't be read. The number of skipped rows is printed to stderr
+    #[arg(long, default_value = "fail")]
+    pub on_error: OnError,
+}
+
+#[derive(clap::Args)]
+#[command(next_help_heading = "Clustering")]
+pub struct ClusterArgs {
+    #[command(flatten)]
+    pub init: InitArgs,
+
+    // Required, unless a subcommand is given
+    #[arg(short, long, required = true)]
+    pub mode: Option<Mode>,
+
+    /// Worker threads of the parallel modes: std threads, tokio workers or the rayon pool.
+    /// Defaults to the available parallelism
     #[arg(short, long)]
-    pub mode: Mode,
+    pub threads: Option<NonZeroUsize>,
 
-    #[arg(short, long, default_value = "true")]
-    pub replace_entry: bool,
+    #[command(flatten)]
+    pub preprocessing: PreprocessingArgs,
+
+    /// Segment into about K superpixels with SLIC instead of running kmeans.
+    /// Labels must be `X:Y` pixel positions
+    #[arg(long, default_value = "false", conflicts_with = "spatial_weight")]
+    pub slic: bool,
+
+    /// SLIC compactness, higher values give more regular superpixels
+    #[arg(long, default_value = "10")]
+    pub compactness: f64,
+
+    /// What replaces each pixel in SLIC mode
+    #[arg(long, default_value = "mean-color")]
+    pub slic_output: SlicOutput,
+
+    /// Cluster each distinct value once, weighted by how often it occurs, then give every
+    /// entry the cluster of its value. Same result on integer values such as colors, much
+    /// faster on images with few colors
+    #[arg(long, default_value = "false", conflicts_with_all = ["spatial_weight", "slic"])]
+    pub dedup: bool,
+
+    /// Write the centers, cluster weights, inertia, largest center shift and elapsed time
+    /// of every iteration to this JSON file
+    #[arg(long, conflicts_with = "slic")]
+    pub trace_out: Option<PathBuf>,
+}
+
+// Number of clusters and how their initial centers are chosen
+#[derive(clap::Args)]
+pub struct InitArgs {
+    #[arg(short, long, default_value = "2", value_parser = parse_k)]
+    pub k: usize,
 
     #[arg(short, long, default_value = "false")]
     pub random_initial: bool,
 
+    /// Seed of the random initialization. A random seed is drawn and printed when missing
+    #[arg(long)]
+    pub seed: Option<u64>,
+}
+
+impl InitArgs {
+    /// Generator of the random initial centers, `None` without `--random-initial`.
+    /// The seed actually used is printed to stderr so the run can be reproduced
+    pub fn rng(&self) -> Option<StdRng> {
+        self.random_initial.then(|| {
+            let seed = self.seed.unwrap_or_else(rand::random);
+            eprintln!("seed: {seed}");
+            StdRng::seed_from_u64(seed)
+        })
+    }
+}
+
+// Features the points are clustered on
+#[derive(clap::Args)]
+pub struct PreprocessingArgs {
+    /// Color space the clustering runs in
+    #[arg(short, long, default_value = "rgb")]
+    pub color_space: ColorSpace,
+
+    /// Append the pixel position parsed from the `X:Y` label as features, scaled by this weight.
+    /// A weight of 1 makes one pixel of distance count as much as one unit of color
+    #[arg(short, long)]
+    pub spatial_weight: Option<f64>,
+}
+
+impl PreprocessingArgs {
+    pub fn preprocessing(&self) -> Preprocessing {
+        Preprocessing {
+            color_space: self.color_space,
+            spatial_weight: self.spatial_weight,
+        }
+    }
+}
+
+#[derive(clap::Args)]
+#[command(next_help_heading = "Input and output")]
+pub struct IoArgs {
+    #[arg(short, long)]
+    pub output_file: Option<PathBuf>,
+
+    /// Replace each entry by its cluster center. When false, each label is written
+    /// with the index of its cluster instead
+    #[arg(long, default_value = "true", action = ArgAction::Set)]
+    pub replace_entry: bool,
+
+    /// With `--replace-entry false`, add the distance to the cluster center
+    #[arg(long, default_value = "false")]
+    pub with_distance: bool,
+
+    /// With `--replace-entry false`, add the original values of the entry
+    #[arg(long, default_value = "false")]
+    pub with_values: bool,
+
+    /// Write the cluster centers and sizes to this file, as CSV, JSON,
+    /// GIMP palette (.gpl) or Adobe swatch exchange (.ase) depending on the extension
+    #[arg(short, long)]
+    pub palette_out: Option<PathBuf>,
+
+    /// Format of the output, instead of the one of `--format` or of the output file extension
+    #[arg(long)]
+    pub output_format: Option<Format>,
+
+    /// Write the cluster centers to this file, as a float64 NumPy array
+    #[arg(long)]
+    pub centroids_out: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub input: InputArgs,
+}
+
+impl IoArgs {
+    pub fn output_format(&self) -> Format {
+        self.output_format
+            .or_else(|| self.output_file.as_deref().and_then(Format::from_path))
+            .or(self.input.format)
+            .unwrap_or(Format::Csv)
+    }
+}
+
+#[derive(clap::Args)]
+#[command(next_help_heading = "Input and output")]
+pub struct InputArgs {
+    /// Input has a ground-truth class column, right after the `X:Y` label by default.
+    /// External validation metrics are printed to stderr
+    #[arg(short, long, default_value = "false")]
+    pub ground_truth: bool,
+
+    /// Column of the ground-truth class in CSV input, by 0-based index or by header name
+    #[arg(long, requires = "ground_truth")]
+    pub class_column: Option<Column>,
+
+    /// Format of STDIN, STDOUT and files without a known extension. Defaults to CSV
+    #[arg(short, long)]
+    pub format: Option<Format>,
+
+    /// Format of the input, instead of the one of `--format` or of the input file extension
+    #[arg(long)]
+    pub input_format: Option<Format>,
+
+    /// What to do with rows that can't be read. The number of skipped rows is printed to stderr
+    #[arg(long, default_value = "fail")]
+    pub on_error: OnError,
+
+    /// Field separator of CSV input, a single character such as `,` or `\t`
+    #[arg(long, default_value = " ", value_parser = parse_delimiter)]
+    pub delimiter: u8,
+
+    /// The first CSV row names the columns
+    #[arg(long, default_value = "false")]
+    pub has_headers: bool,
+
+    /// Column of the entry label, by 0-based index or by header name
+    #[arg(long, default_value = "0")]
+    pub label_column: Column,
+
+    /// Comma separated columns of floating-point features, by 0-based index or by header name.
+    /// By default the three columns after the label (and class) are integer RGB values in 0-255
+    #[arg(long, value_delimiter = ',')]
+    pub feature_columns: Option<Vec<Column>>,
+
+    /// Column of a positive weight per entry, by 0-based index or by header name.
+    /// An entry of weight 2 counts as much as two identical entries
+    #[arg(long)]
+    pub weight_column: Option<Column>,
+
     /// Main entry
     pub input_file: Option<PathBuf>,
 }
 
+impl InputArgs {
+    pub fn input_format(&self) -> Format {
+        self.input_format
+            .or_else(|| self.input_file.as_deref().and_then(Format::from_path))
+            .or(self.format)
+            .unwrap_or(Format::Csv)
+    }
+
+    pub fn layout(&self) -> Layout {
+        Layout {
+            delimiter: self.delimiter,
+            has_headers: self.has_headers,
+            label: self.label_column.clone(),
+            ground_truth: self.ground_truth,
+            class: self.class_column.clone(),
+            features: self.feature_columns.clone(),
+            weight: self.weight_column.clone(),
+        }
+    }
+
+    /// Entries are RGB colors rather than arbitrary features
+    pub fn is_color(&self) -> bool {
+        let format = self.input_format();
+        self.feature_columns.is_none() && (format == Format::Csv || format.is_image())
+    }
+}
+
+fn parse_k(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(0) => Err("k must be at least 1".to_string()),
+        Ok(k) => Ok(k),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+fn parse_delimiter(s: &str) -> Result<u8, String> {
+    match s {
+        "\\t" | "tab" => Ok(b'\t'),
+        _ => match s.as_bytes() {
+            [byte] => Ok(*byte),
+            _ => Err("the delimiter must be a single ASCII character".to_string()),
+        },
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug, Hash)]
 pub enum Mode {
     /// Run in serial
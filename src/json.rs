@@ -0,0 +1,179 @@
+use std::error::Error;
+use std::io::{BufRead, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::rows::{InputError, InputRow, RowErrors};
+
+/// One NDJSON input line
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonPoint {
+    label: serde_json::Value,
+    #[serde(default)]
+    class: Option<serde_json::Value>,
+    values: Vec<f64>,
+    #[serde(default = "default_weight")]
+    weight: f64,
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+/// Strings as they are, other values as JSON
+fn to_text(value: serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(text) => text,
+        value => value.to_string(),
+    }
+}
+
+/// Every valid `{"label": ..., "values": [...]}` line of `reader`, with its `class` when
+/// `ground_truth` is set and its optional `weight`. Every point must have as many values
+/// as the first one
+pub fn read_points(
+    reader: impl BufRead,
+    ground_truth: bool,
+    errors: &mut RowErrors,
+) -> Result<Vec<InputRow>, InputError> {
+    let mut rows: Vec<InputRow> = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line_number = index as u64 + 1;
+        let parse_error = |message: String| InputError::Parse {
+            line: line_number,
+            content: line.clone(),
+            message,
+        };
+
+        let row = serde_json::from_str::<JsonPoint>(&line)
+            .map_err(|error| parse_error(error.to_string()))
+            .and_then(|point| {
+                let dimension = rows.first().map_or(point.values.len(), |row| row.2.len());
+                if point.values.len() != dimension || dimension == 0 {
+                    return Err(parse_error(format!(
+                        "expected {dimension} values, found {}",
+                        point.values.len()
+                    )));
+                }
+                if !(point.weight.is_finite() && point.weight > 0.0) {
+                    return Err(parse_error("`weight` must be positive".to_string()));
+                }
+                let class = match (ground_truth, point.class) {
+                    (true, None) => return Err(parse_error("missing `class`".to_string())),
+                    (true, class) => class.map(to_text),
+                    (false, _) => None,
+                };
+                Ok((to_text(point.label), class, point.values, point.weight))
+            });
+        if let Some(row) = errors.handle(row)? {
+            rows.push(row);
+        }
+    }
+    if rows.is_empty() {
+        return Err(InputError::Empty);
+    }
+    Ok(rows)
+}
+
+/// Cluster of one entry, written as an NDJSON line or in a [`Document`]
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Assignment<'a> {
+    pub label: &'a str,
+    pub cluster: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance: Option<f64>,
+    /// Values of the center with `--replace-entry`, otherwise the ones of the entry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub values: Option<Vec<f64>>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub points: usize,
+    pub iterations: usize,
+    /// Sum of the squared distances of the entries to their center, times their weight
+    pub inertia: f64,
+    pub elapsed_ms: f64,
+}
+
+/// JSON output: the centers and their sizes, every assignment and how the run went
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Document<'a> {
+    pub centroids: Vec<Vec<f64>>,
+    pub sizes: Vec<usize>,
+    pub assignments: Vec<Assignment<'a>>,
+    pub stats: Stats,
+}
+
+pub fn write_ndjson<'a>(
+    mut writer: impl Write,
+    assignments: impl IntoIterator<Item = Assignment<'a>>,
+) -> Result<(), Box<dyn Error>> {
+    for assignment in assignments {
+        serde_json::to_writer(&mut writer, &assignment)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rows::OnError;
+
+    #[test]
+    fn test_read_points() {
+        let text = r#"{"label": "a", "values": [1, 2.5]}
+{"label": 7, "class": "x", "values": [3, 4], "weight": 2}
+
+{"label": "c", "values": [5]}
+{"label": "d", "value": [5, 6]}
+"#;
+        let mut errors = RowErrors::new(OnError::Skip);
+        let rows = read_points(text.as_bytes(), false, &mut errors).unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                ("a".to_string(), None, vec![1.0, 2.5], 1.0),
+                ("7".to_string(), None, vec![3.0, 4.0], 2.0),
+            ]
+        );
+        assert_eq!(errors.skipped(), 2);
+
+        let mut errors = RowErrors::new(OnError::Fail);
+        let error = read_points(text.as_bytes(), true, &mut errors).unwrap_err();
+        assert_eq!(error.line(), Some(1));
+        assert!(error.to_string().contains("missing `class`"));
+    }
+
+    #[test]
+    fn test_write_ndjson() {
+        let assignments = [
+            Assignment {
+                label: "a",
+                cluster: 1,
+                distance: None,
+                values: None,
+            },
+            Assignment {
+                label: "b",
+                cluster: 0,
+                distance: Some(0.5),
+                values: Some(vec![1.0, 2.5]),
+            },
+        ];
+        let mut bytes = Vec::new();
+        write_ndjson(&mut bytes, assignments).unwrap();
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            "{\"label\":\"a\",\"cluster\":1}\n\
+             {\"label\":\"b\",\"cluster\":0,\"distance\":0.5,\"values\":[1.0,2.5]}\n"
+        );
+    }
+}
@@ -1,11 +1,40 @@
+use std::ops::ControlFlow;
+use std::time::Instant;
+
 use rand::seq::SliceRandom;
+use rand::Rng;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
 use crate::entities::{Cluster, Point};
 
-pub fn get_n_random_points(points: &[Point], n: usize) -> Vec<Point> {
+use super::{Iteration, Observer};
+
+/// Squared distance under which a center is considered unchanged between iterations
+const CONVERGENCE_TOLERANCE: f64 = 1e-9;
+
+/// Dedicated rayon pool of `max_threads` threads, or one per CPU when 0
+pub fn thread_pool(max_threads: usize) -> rayon::ThreadPool {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(max_threads)
+        .build()
+        .expect("failed to build the rayon thread pool")
+}
+
+/// Multi-threaded tokio runtime of `max_threads` workers, or one per CPU when 0
+pub fn runtime(max_threads: usize) -> tokio::runtime::Runtime {
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    if max_threads > 0 {
+        builder.worker_threads(max_threads);
+    }
+    builder
+        .enable_all()
+        .build()
+        .expect("failed to build the tokio runtime")
+}
+
+pub fn get_n_random_points(points: &[Point], n: usize, rng: &mut impl Rng) -> Vec<Point> {
     let mut points = points.to_vec();
-    points.shuffle(&mut rand::thread_rng());
+    points.shuffle(rng);
     points.iter().take(n).cloned().collect()
 }
 
@@ -13,7 +42,7 @@ pub fn get_closest_cluster_index<'a>(
     point: &Point,
     clusters: impl IntoIterator<Item = &'a Cluster<'a>>,
 ) -> usize {
-    let mut min_distance = u32::MAX;
+    let mut min_distance = f64::MAX;
     let mut index = 0;
     for (i, cluster) in clusters.into_iter().enumerate() {
         let distance = point.euclidean_distance(&cluster.center);
@@ -25,7 +54,23 @@ pub fn get_closest_cluster_index<'a>(
     index
 }
 
-pub fn assign_points<'a>(data: &'a Vec<Point>, mut clusters: Vec<Cluster<'a>>) -> Vec<Cluster<'a>> {
+pub fn get_closest_cluster_index_based_in_centroids<'a>(
+    point: &Point,
+    centroids: impl IntoIterator<Item = &'a Point>,
+) -> usize {
+    let mut min_distance = f64::MAX;
+    let mut index = 0;
+    for (i, cluster) in centroids.into_iter().enumerate() {
+        let distance = point.euclidean_distance(cluster);
+        if distance < min_distance {
+            min_distance = distance;
+            index = i;
+        }
+    }
+    index
+}
+
+pub fn assign_points<'a>(data: &'a [Point], mut clusters: Vec<Cluster<'a>>) -> Vec<Cluster<'a>> {
     for point in data {
         let index = get_closest_cluster_index(point, &clusters);
         clusters[index].points.push(point);
@@ -34,14 +79,131 @@ pub fn assign_points<'a>(data: &'a Vec<Point>, mut clusters: Vec<Cluster<'a>>) -
     clusters
 }
 
+/// Index of the closest cluster of each point, in input order
+pub fn get_assignments(data: &[Point], clusters: &[Cluster]) -> Vec<usize> {
+    data.par_iter()
+        .map(|point| get_closest_cluster_index(point, clusters))
+        .collect()
+}
+
+/// Puts points back in input order. They all reference the input slice, so their
+/// addresses follow the input order whatever thread pushed them
+pub fn sort_in_input_order(points: &mut [&Point]) {
+    points.sort_unstable_by_key(|point| *point as *const Point);
+}
+
+/// Every point of `clusters` with the index of its cluster, in input order
+pub fn assignments_in_input_order<'a>(clusters: &[Cluster<'a>]) -> Vec<(&'a Point, usize)> {
+    let mut assignments: Vec<(&Point, usize)> = clusters
+        .iter()
+        .enumerate()
+        .flat_map(|(index, cluster)| cluster.points.iter().map(move |&point| (point, index)))
+        .collect();
+    assignments.sort_unstable_by_key(|&(point, _)| point as *const Point);
+    assignments
+}
+
+/// Position of `point` in `data`, which it must reference
+pub fn input_index(data: &[Point], point: &Point) -> usize {
+    (point as *const Point as usize - data.as_ptr() as usize) / std::mem::size_of::<Point>()
+}
+
+/// Reports how a run goes to its observer, and logs a summary of every iteration at
+/// info level (`-v`) and the centers at debug level (`-vv`). Every implementation
+/// goes through it, and nothing is computed when there's no one to tell
+pub struct Progress<'d, 'o> {
+    data: &'d [Point],
+    observer: Option<&'o mut dyn Observer>,
+    start: Instant,
+    iteration: usize,
+    /// Cluster of every point in the previous iteration, `usize::MAX` before the first
+    assignments: Vec<usize>,
+}
+
+impl<'d, 'o> Progress<'d, 'o> {
+    pub fn new(
+        data: &'d [Point],
+        initial_centers: &[Point],
+        observer: Option<&'o mut dyn Observer>,
+    ) -> Self {
+        log::debug!("initial centers: {:?}", data_of(initial_centers));
+        Self {
+            data,
+            observer,
+            start: Instant::now(),
+            iteration: 0,
+            assignments: Vec::new(),
+        }
+    }
+
+    /// Called once the points are assigned to `clusters`, whose next centers are `new_centers`.
+    /// Breaks when the observer wants the run to stop
+    pub fn iteration<'c>(
+        &mut self,
+        clusters: impl IntoIterator<Item = &'c Cluster<'c>>,
+        new_centers: &[Point],
+    ) -> ControlFlow<()> {
+        self.iteration += 1;
+        let elapsed = self.start.elapsed();
+        let logged = log::log_enabled!(log::Level::Info);
+        if !logged && self.observer.is_none() {
+            return ControlFlow::Continue(());
+        }
+        if logged && self.assignments.is_empty() {
+            self.assignments = vec![usize::MAX; self.data.len()];
+        }
+
+        let mut weights = Vec::with_capacity(new_centers.len());
+        let mut inertia = 0.0;
+        // Weighted, like the sizes, so that a deduplicated run reports what a plain one does
+        let mut moved = 0.0;
+        let mut max_shift: f64 = 0.0;
+        for ((index, cluster), new_center) in clusters.into_iter().enumerate().zip(new_centers) {
+            weights.push(cluster.points.iter().map(|point| point.get_weight()).sum());
+            max_shift = max_shift.max(cluster.center.euclidean_distance(new_center).sqrt());
+            for &point in &cluster.points {
+                inertia += point.get_weight() * point.euclidean_distance(&cluster.center);
+                if logged {
+                    let previous = &mut self.assignments[input_index(self.data, point)];
+                    if *previous != index {
+                        moved += point.get_weight();
+                        *previous = index;
+                    }
+                }
+            }
+        }
+        log::info!(
+            "iteration {}: inertia {inertia:.4}, max center shift {max_shift:.6}, {moved} points moved",
+            self.iteration
+        );
+        log::debug!("centers: {:?}", data_of(new_centers));
+
+        match self.observer.as_deref_mut() {
+            Some(observer) => observer.iteration(&Iteration {
+                index: self.iteration,
+                centers: new_centers,
+                weights,
+                inertia,
+                max_shift,
+                elapsed,
+            }),
+            None => ControlFlow::Continue(()),
+        }
+    }
+}
+
+fn data_of(points: &[Point]) -> Vec<&[f64]> {
+    points.iter().map(Point::get_data).collect()
+}
+
 pub fn converged<'a>(
     points1: impl IntoIterator<Item = &'a Point>,
     points2: impl IntoIterator<Item = &'a Point>,
 ) -> bool {
     points1
         .into_iter()
-        .zip(points2.into_iter())
-        .all(|(p1, p2)| p1 == p2)
+        .zip(points2)
+        .all(|(p1, p2)| p1.euclidean_distance(p2) <= CONVERGENCE_TOLERANCE)
 }
 
 pub fn calculate_new_centers<'a>(
@@ -53,9 +215,33 @@ pub fn calculate_new_centers<'a>(
         .collect()
 }
 
-pub fn calculate_new_centers_parallel(cluster: &Vec<Cluster>) -> Vec<Point> {
+pub fn calculate_new_centers_parallel(cluster: &[Cluster]) -> Vec<Point> {
     cluster
         .par_iter()
         .map(|cluster| cluster.calculate_center_point())
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn test_random_points_are_reproducible() {
+        let points: Vec<Point> = (0..100).map(|n| Point::from([n, n])).collect();
+        let pick = |seed| get_n_random_points(&points, 5, &mut StdRng::seed_from_u64(seed));
+
+        assert_eq!(pick(42), pick(42));
+        assert_ne!(pick(42), pick(43));
+    }
+
+    #[test]
+    fn test_input_index() {
+        let points: Vec<Point> = (0..4).map(|n| Point::from([n])).collect();
+        assert_eq!(input_index(&points, &points[0]), 0);
+        assert_eq!(input_index(&points, &points[3]), 3);
+    }
+}
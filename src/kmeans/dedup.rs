@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use crate::entities::{Cluster, Point};
+
+use super::{common, Execution, Kmeans, Observer};
+
+/// Runs `inner` on the distinct points only, each weighing as much as all its copies,
+/// then puts every original point back in the cluster of its copy.
+/// Images usually have far fewer colors than pixels
+pub struct Deduplicated {
+    pub inner: Box<dyn Kmeans>,
+}
+
+impl Kmeans for Deduplicated {
+    fn run_observed<'a>(
+        &self,
+        data: &'a [Point],
+        k: usize,
+        initial_centers: Vec<Point>,
+        observer: Option<&mut dyn Observer>,
+    ) -> Execution<'a> {
+        let mut first: Vec<&Point> = Vec::new();
+        let mut weights: Vec<f64> = Vec::new();
+        let mut index_by_values: HashMap<Vec<u64>, usize> = HashMap::new();
+        let unique_index: Vec<usize> = data
+            .iter()
+            .map(|point| {
+                let index = *index_by_values
+                    .entry(point.get_values())
+                    .or_insert_with(|| {
+                        first.push(point);
+                        weights.push(0.0);
+                        first.len() - 1
+                    });
+                weights[index] += point.get_weight();
+                index
+            })
+            .collect();
+
+        let unique: Vec<Point> = first
+            .into_iter()
+            .zip(weights)
+            .map(|(point, weight)| Point::from_vec(point.get_data().to_vec()).with_weight(weight))
+            .collect();
+        let execution = self
+            .inner
+            .run_observed(&unique, k, initial_centers, observer);
+
+        let mut cluster_of_unique = vec![0; unique.len()];
+        for (index, cluster) in execution.clusters.iter().enumerate() {
+            for &point in &cluster.points {
+                cluster_of_unique[common::input_index(&unique, point)] = index;
+            }
+        }
+
+        let mut clusters: Vec<Cluster> = execution
+            .clusters
+            .into_iter()
+            .map(|cluster| Cluster::from_center(cluster.center))
+            .collect();
+        for (point, &index) in data.iter().zip(&unique_index) {
+            clusters[cluster_of_unique[index]].points.push(point);
+        }
+        Execution {
+            clusters,
+            iterations: execution.iterations,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parallel_2::KmeansParallelBuilder2;
+    use super::super::serial::KmeansSerialBuilder;
+    use super::super::Iteration;
+    use super::*;
+    use std::ops::ControlFlow;
+
+    #[test]
+    fn test_same_clusters_as_without_deduplication() {
+        let data: Vec<Point> = (0..300)
+            .map(|n| Point::from([(n * 7) % 5, (n * 3) % 4, 2]).with_label(&n.to_string()))
+            .collect();
+        let initial_centers = vec![Point::from([0, 0, 2]), Point::from([4, 3, 2])];
+
+        let runners: [Box<dyn Kmeans>; 2] = [
+            Box::new(KmeansSerialBuilder),
+            Box::new(KmeansParallelBuilder2::new(3)),
+        ];
+        for runner in runners {
+            let mut expected_weights = Vec::new();
+            let expected = runner.run_observed(
+                &data,
+                2,
+                initial_centers.clone(),
+                Some(&mut |iteration: &Iteration| {
+                    expected_weights.push(iteration.weights.clone());
+                    ControlFlow::Continue(())
+                }),
+            );
+            let mut weights = Vec::new();
+            let deduplicated = Deduplicated { inner: runner }.run_observed(
+                &data,
+                2,
+                initial_centers.clone(),
+                Some(&mut |iteration: &Iteration| {
+                    weights.push(iteration.weights.clone());
+                    ControlFlow::Continue(())
+                }),
+            );
+            assert_eq!(deduplicated.clusters, expected.clusters);
+            assert_eq!(deduplicated.iterations, expected.iterations);
+            assert_eq!(weights, expected_weights);
+        }
+    }
+}
@@ -1,3 +1,6 @@
+use std::ops::ControlFlow;
+use std::time::Duration;
+
 use crate::entities::{Cluster, Point};
 
 pub mod parallel;
@@ -8,12 +11,246 @@ pub mod serial;
 pub mod tokio;
 
 pub mod common;
+pub mod dedup;
+
+/// Result of a run: the final clusters and the number of assignment passes it took
+pub struct Execution<'a> {
+    pub clusters: Vec<Cluster<'a>>,
+    pub iterations: usize,
+}
+
+/// How one iteration went, once the points are assigned and the centers updated
+pub struct Iteration<'e> {
+    /// 1 for the first iteration
+    pub index: usize,
+    /// Updated centers, in feature space
+    pub centers: &'e [Point],
+    /// Total weight of the points assigned to each center, their number when they are
+    /// not weighted. A point standing for several copies counts as all of them
+    pub weights: Vec<f64>,
+    /// Weighted sum of the squared distances of the points to the center they were assigned to
+    pub inertia: f64,
+    /// Largest distance a center moved by
+    pub max_shift: f64,
+    /// Since the start of the run
+    pub elapsed: Duration,
+}
+
+/// Receives every [`Iteration`] of a run, and can stop it before it converges by
+/// returning [`ControlFlow::Break`]. The run then returns the clusters of that iteration
+pub trait Observer: Send {
+    fn iteration(&mut self, iteration: &Iteration) -> ControlFlow<()>;
+}
+
+impl<F> Observer for F
+where
+    F: FnMut(&Iteration) -> ControlFlow<()> + Send,
+{
+    fn iteration(&mut self, iteration: &Iteration) -> ControlFlow<()> {
+        self(iteration)
+    }
+}
 
+/// Lloyd's kmeans over `data`, starting from `initial_centers`. Every implementation
+/// weighs each point by [`Point::get_weight`]: a point of weight 3 moves its center
+/// as much as three copies of it
 pub trait Kmeans {
+    /// Runs kmeans, reporting every iteration to `observer` when given, until the centers
+    /// converge or the observer stops it
+    fn run_observed<'a>(
+        &self,
+        data: &'a [Point],
+        k: usize,
+        initial_centers: Vec<Point>,
+        observer: Option<&mut dyn Observer>,
+    ) -> Execution<'a>;
+
+    fn run<'a>(&self, data: &'a [Point], k: usize, initial_centers: Vec<Point>) -> Execution<'a> {
+        self.run_observed(data, k, initial_centers, None)
+    }
+
+    /// Clusters only, for callers that don't need the statistics of the run
+    #[allow(dead_code)]
     fn execute<'a>(
         &self,
-        data: &'static Vec<Point>,
-        k: u8,
+        data: &'a [Point],
+        k: usize,
         initial_centers: Vec<Point>,
-    ) -> Vec<Cluster<'a>>;
+    ) -> Vec<Cluster<'a>> {
+        self.run(data, k, initial_centers).clusters
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::parallel::KmeansParallelBuilder;
+    use super::parallel_2::KmeansParallelBuilder2;
+    use super::parallel_3::KmeansParallelStdBuilder;
+    use super::parallel_mutex::KmeansParallelMutex;
+    use super::serial::KmeansSerialBuilder;
+    use super::tokio::KmeansTokioBuilder;
+    use super::*;
+
+    const THREADS: usize = 3;
+
+    fn parallel_implementations() -> Vec<(&'static str, Box<dyn Kmeans>)> {
+        vec![
+            (
+                "par",
+                Box::new(KmeansParallelStdBuilder {
+                    max_threads: THREADS,
+                }),
+            ),
+            (
+                "mutex",
+                Box::new(KmeansParallelMutex {
+                    max_threads: THREADS,
+                }),
+            ),
+            ("ray", Box::new(KmeansParallelBuilder2::new(THREADS))),
+            ("ray2", Box::new(KmeansParallelBuilder::new(THREADS))),
+            ("tokio", Box::new(KmeansTokioBuilder::new(THREADS))),
+        ]
+    }
+
+    /// Runs every implementation from the same centers and checks it returns exactly
+    /// the clusters and iteration count of the serial one
+    fn assert_modes_agree(data: &[Point], initial_centers: Vec<Point>) -> Vec<Cluster<'_>> {
+        let k = initial_centers.len();
+        let serial = KmeansSerialBuilder.run(data, k, initial_centers.clone());
+        assert_eq!(serial.clusters.len(), initial_centers.len());
+
+        for (name, implementation) in parallel_implementations() {
+            let execution = implementation.run(data, k, initial_centers.clone());
+            assert_eq!(execution.clusters, serial.clusters, "{name}");
+            assert_eq!(execution.iterations, serial.iterations, "{name}");
+        }
+        serial.clusters
+    }
+
+    fn points(coordinates: &[[i32; 2]]) -> Vec<Point> {
+        coordinates
+            .iter()
+            .map(|&values| Point::from(values))
+            .collect()
+    }
+
+    #[test]
+    fn test_single_point() {
+        let data = points(&[[3, 4]]);
+        let clusters = assert_modes_agree(&data, data.clone());
+        assert_eq!(clusters[0].points.len(), 1);
+    }
+
+    #[test]
+    fn test_k_equal_to_n() {
+        let data = points(&[[0, 0], [5, 5], [10, 0], [0, 10]]);
+        let clusters = assert_modes_agree(&data, data.clone());
+        assert!(clusters.iter().all(|cluster| cluster.points.len() == 1));
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let data = points(&[[1, 1], [1, 1], [1, 1], [7, 7], [7, 7], [1, 1]]);
+        // Equal initial centers: ties go to the first one, leaving the other empty
+        let clusters = assert_modes_agree(&data, points(&[[1, 1], [1, 1], [7, 7]]));
+        assert_eq!(
+            clusters.iter().map(|c| c.points.len()).collect::<Vec<_>>(),
+            [4, 0, 2]
+        );
+    }
+
+    #[test]
+    fn test_empty_cluster() {
+        let data = points(&[[10, 10], [11, 11], [12, 12]]);
+        let clusters = assert_modes_agree(&data, points(&[[11, 11], [100, 100]]));
+        assert!(clusters.iter().any(|cluster| cluster.points.is_empty()));
+    }
+
+    #[test]
+    fn test_weights_count_as_copies() {
+        let weighted = vec![
+            Point::from([0, 0]).with_weight(3.0),
+            Point::from([4, 0]),
+            Point::from([40, 40]).with_weight(0.5),
+            Point::from([44, 40]).with_weight(1.5),
+        ];
+        let initial_centers = points(&[[0, 0], [40, 40]]);
+        let clusters = assert_modes_agree(&weighted, initial_centers.clone());
+
+        let copies = points(&[
+            [0, 0],
+            [0, 0],
+            [0, 0],
+            [4, 0],
+            [40, 40],
+            [44, 40],
+            [44, 40],
+            [44, 40],
+        ]);
+        let expected = KmeansSerialBuilder.execute(&copies, 2, initial_centers);
+        for (cluster, expected) in clusters.iter().zip(&expected) {
+            assert_eq!(cluster.center, expected.center);
+        }
+        assert_eq!(clusters[1].center, Point::from([43, 40]));
+    }
+
+    #[test]
+    fn test_observer_stops_the_run() {
+        let data = points(&[[0, 0], [1, 0], [10, 0], [11, 0]]);
+        let initial_centers = points(&[[0, 0], [1, 0]]);
+
+        let mut implementations = parallel_implementations();
+        implementations.push(("serial", Box::new(KmeansSerialBuilder)));
+        for (name, implementation) in implementations {
+            let mut events = Vec::new();
+            let mut stop_after_first = |iteration: &Iteration| {
+                events.push((iteration.index, iteration.weights.clone()));
+                ControlFlow::Break(())
+            };
+            let execution = implementation.run_observed(
+                &data,
+                2,
+                initial_centers.clone(),
+                Some(&mut stop_after_first),
+            );
+
+            assert_eq!(events, vec![(1, vec![1.0, 3.0])], "{name}");
+            assert_eq!(execution.iterations, 1, "{name}");
+            assert_eq!(execution.clusters[0].center, Point::from([0, 0]), "{name}");
+            assert_eq!(execution.clusters[1].points.len(), 3, "{name}");
+        }
+    }
+
+    /// Small integer coordinates, so that duplicates and distance ties are frequent
+    fn dataset() -> impl Strategy<Value = (Vec<Point>, Vec<Point>)> {
+        (1..4usize, 1..40usize)
+            .prop_flat_map(|(dimension, len)| {
+                (
+                    prop::collection::vec(prop::collection::vec(-10..10i32, dimension), len),
+                    prop::collection::vec(0..len, 1..=len),
+                )
+            })
+            .prop_map(|(values, centers)| {
+                let data: Vec<Point> = values
+                    .into_iter()
+                    .map(|values| Point::from_vec(values.into_iter().map(f64::from).collect()))
+                    .collect();
+                let initial_centers = centers.iter().map(|&i| data[i].clone()).collect();
+                (data, initial_centers)
+            })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn test_modes_agree_with_serial((data, initial_centers) in dataset()) {
+            let len = data.len();
+            let clusters = assert_modes_agree(&data, initial_centers);
+            prop_assert_eq!(clusters.iter().map(|c| c.points.len()).sum::<usize>(), len);
+        }
+    }
 }
@@ -6,65 +6,86 @@ use rayon::prelude::*;
 
 use crate::entities::{Cluster, Point};
 
-use super::{common, Kmeans};
+use super::{common, Execution, Kmeans, Observer};
 
-#[derive(Default)]
-pub struct KmeansParallelBuilder;
+pub struct KmeansParallelBuilder {
+    /// Shared by every run, so that they don't pay for starting threads
+    pool: rayon::ThreadPool,
+}
+
+impl KmeansParallelBuilder {
+    /// `max_threads` is the size of the rayon pool, 0 for one thread per CPU
+    pub fn new(max_threads: usize) -> Self {
+        Self {
+            pool: common::thread_pool(max_threads),
+        }
+    }
+}
 
 impl Kmeans for KmeansParallelBuilder {
-    fn execute<'a>(
+    fn run_observed<'a>(
         &self,
-        data: &'static Vec<Point>,
-        _k: u8,
+        data: &'a [Point],
+        _k: usize,
         initial_centers: Vec<Point>,
-    ) -> Vec<Cluster<'a>> {
-        let mut clusters = initial_centers
-            .into_iter()
-            .map(|center| Cluster::from_center(center))
-            .collect::<Vec<Cluster>>();
-
-        let mut i: u64 = 1;
-        loop {
-            eprintln!("iteration {}", i);
-            let clusters_to_read = clusters.clone();
-            let clusters_to_write = &mut clusters;
-
-            let (tx, rx) = mpsc::channel::<(&Point, usize)>();
-
-            rayon::scope(move |scope| {
-                scope.spawn(move |_| {
-                    while let Ok((point, index)) = rx.recv() {
-                        eprintln!("> {:?}", point.get_label());
-                        clusters_to_write[index].points.push(point);
-                    }
-                });
+        observer: Option<&mut dyn Observer>,
+    ) -> Execution<'a> {
+        self.pool
+            .install(|| run_in_pool(data, initial_centers, observer))
+    }
+}
+
+fn run_in_pool<'a>(
+    data: &'a [Point],
+    initial_centers: Vec<Point>,
+    observer: Option<&mut dyn Observer>,
+) -> Execution<'a> {
+    let mut progress = common::Progress::new(data, &initial_centers, observer);
+    let mut clusters = initial_centers
+        .into_iter()
+        .map(Cluster::from_center)
+        .collect::<Vec<Cluster>>();
+
+    let mut iterations = 0;
+    loop {
+        let clusters_to_read = clusters.clone();
+        let clusters_to_write = &mut clusters;
+
+        let (tx, rx) = mpsc::channel::<(&Point, usize)>();
 
-                scope.spawn(move |_| {
-                    data.par_iter().for_each(|point| {
-                        eprintln!("b {:?}", point.get_label());
-                        let index = common::get_closest_cluster_index(&point, &clusters_to_read);
-                        tx.send((point, index)).unwrap();
-                        eprintln!("e {:?}", point.get_label());
-                    });
+        rayon::scope(move |scope| {
+            scope.spawn(move |_| {
+                while let Ok((point, index)) = rx.recv() {
+                    clusters_to_write[index].points.push(point);
+                }
+            });
+
+            scope.spawn(move |_| {
+                data.par_iter().for_each(|point| {
+                    let index = common::get_closest_cluster_index(point, &clusters_to_read);
+                    tx.send((point, index)).unwrap();
                 });
             });
+        });
 
-            i = i.saturating_add(1);
+        // Points arrive in whichever order the workers send them
+        for cluster in &mut clusters {
+            common::sort_in_input_order(&mut cluster.points);
+        }
 
-            let new_centers: Vec<Point> = common::calculate_new_centers_parallel(&clusters);
-            let old_centers: Vec<_> = clusters.iter().map(|cluster| &cluster.center).collect();
+        iterations += 1;
 
-            if common::converged(new_centers.iter(), old_centers) {
-                return clusters;
-            }
+        let new_centers: Vec<Point> = common::calculate_new_centers_parallel(&clusters);
+        let stop = progress.iteration(&clusters, &new_centers).is_break();
+        let old_centers: Vec<_> = clusters.iter().map(|cluster| &cluster.center).collect();
 
-            clusters = new_centers
-                .into_iter()
-                .map(|center| Cluster::from_center(center))
-                .collect();
+        if stop || common::converged(new_centers.iter(), old_centers) {
+            return Execution {
+                clusters,
+                iterations,
+            };
         }
 
-        // TODO:
-        // The tests are right. The problemn is only the order
+        clusters = new_centers.into_iter().map(Cluster::from_center).collect();
     }
 }
@@ -6,68 +6,89 @@ use rayon::prelude::*;
 
 use crate::entities::{Cluster, Point};
 
-use super::{common, Kmeans};
+use super::{common, Execution, Kmeans, Observer};
 
-#[derive(Default)]
-pub struct KmeansParallelBuilder2;
+pub struct KmeansParallelBuilder2 {
+    /// Shared by every run, so that they don't pay for starting threads
+    pool: rayon::ThreadPool,
+}
+
+impl KmeansParallelBuilder2 {
+    /// `max_threads` is the size of the rayon pool, 0 for one thread per CPU
+    pub fn new(max_threads: usize) -> Self {
+        Self {
+            pool: common::thread_pool(max_threads),
+        }
+    }
+}
 
 impl Kmeans for KmeansParallelBuilder2 {
-    fn execute<'a>(
+    fn run_observed<'a>(
         &self,
-        data: &'static Vec<Point>,
-        _k: u8,
+        data: &'a [Point],
+        _k: usize,
         initial_centers: Vec<Point>,
-    ) -> Vec<Cluster<'a>> {
-        let mut clusters = initial_centers
-            .into_iter()
-            .map(|center| Cluster::from_center(center))
-            .collect::<Vec<Cluster>>();
+        observer: Option<&mut dyn Observer>,
+    ) -> Execution<'a> {
+        self.pool
+            .install(|| run_in_pool(data, initial_centers, observer))
+    }
+}
 
-        let mut i: u64 = 1;
-        loop {
-            clusters = data
-                .par_iter()
-                .map(|point| {
-                    let index = common::get_closest_cluster_index(point, &clusters);
-                    let centroid = &clusters[index].center;
-                    (centroid, point)
-                })
-                .fold_with(
-                    BTreeMap::<&Point, Vec<&Point>>::default(),
-                    |mut acc, (centroid, point)| {
-                        acc.entry(centroid).or_insert_with(Vec::new).push(point);
-                        acc
-                    },
-                )
-                .reduce(
-                    || BTreeMap::<&Point, Vec<&Point>>::default(),
-                    |mut map1, map2| {
-                        for (key, values) in map2 {
-                            map1.entry(key).or_insert_with(Vec::new).extend(values);
-                        }
-                        map1
-                    },
-                )
-                .into_iter()
-                .map(|(k, v)| Cluster {
-                    center: k.clone(),
-                    points: v,
-                })
-                .collect();
+fn run_in_pool<'a>(
+    data: &'a [Point],
+    initial_centers: Vec<Point>,
+    observer: Option<&mut dyn Observer>,
+) -> Execution<'a> {
+    let mut progress = common::Progress::new(data, &initial_centers, observer);
+    let mut clusters = initial_centers
+        .into_iter()
+        .map(Cluster::from_center)
+        .collect::<Vec<Cluster>>();
 
-            i = i.saturating_add(1);
+    let mut iterations = 0;
+    loop {
+        // Keyed by cluster index, so that clusters keep the initial-center order and
+        // empty clusters or equal centers are not lost
+        let mut points_by_cluster = data
+            .par_iter()
+            .map(|point| (common::get_closest_cluster_index(point, &clusters), point))
+            .fold_with(
+                BTreeMap::<usize, Vec<&Point>>::default(),
+                |mut acc, (index, point)| {
+                    acc.entry(index).or_insert_with(Vec::new).push(point);
+                    acc
+                },
+            )
+            .reduce(BTreeMap::<usize, Vec<&Point>>::default, |mut map1, map2| {
+                for (key, values) in map2 {
+                    map1.entry(key).or_insert_with(Vec::new).extend(values);
+                }
+                map1
+            });
 
-            let new_centers: Vec<Point> = common::calculate_new_centers_parallel(&clusters);
-            let old_centers: Vec<_> = clusters.iter().map(|cluster| &cluster.center).collect();
+        clusters = clusters
+            .into_iter()
+            .enumerate()
+            .map(|(index, cluster)| Cluster {
+                center: cluster.center,
+                points: points_by_cluster.remove(&index).unwrap_or_default(),
+            })
+            .collect();
 
-            if common::converged(new_centers.iter(), old_centers) {
-                return clusters;
-            }
+        iterations += 1;
 
-            clusters = new_centers
-                .into_iter()
-                .map(|center| Cluster::from_center(center))
-                .collect();
+        let new_centers: Vec<Point> = common::calculate_new_centers_parallel(&clusters);
+        let stop = progress.iteration(&clusters, &new_centers).is_break();
+        let old_centers: Vec<_> = clusters.iter().map(|cluster| &cluster.center).collect();
+
+        if stop || common::converged(new_centers.iter(), old_centers) {
+            return Execution {
+                clusters,
+                iterations,
+            };
         }
+
+        clusters = new_centers.into_iter().map(Cluster::from_center).collect();
     }
 }
@@ -2,7 +2,7 @@
 
 use crate::entities::{Cluster, Point};
 
-use super::{common, Kmeans};
+use super::{common, Execution, Kmeans, Observer};
 
 use std::sync::mpsc;
 use std::sync::Arc;
@@ -13,84 +13,104 @@ pub struct KmeansParallelStdBuilder {
 }
 
 impl Kmeans for KmeansParallelStdBuilder {
-    fn execute<'a>(
+    fn run_observed<'a>(
         &self,
-        data: &'static Vec<Point>,
-        k: u8,
+        data: &'a [Point],
+        k: usize,
         initial_centers: Vec<Point>,
-    ) -> Vec<Cluster<'a>> {
+        observer: Option<&mut dyn Observer>,
+    ) -> Execution<'a> {
+        let mut progress = common::Progress::new(data, &initial_centers, observer);
         let mut clusters = initial_centers
             .into_iter()
-            .map(|center| Cluster::from_center(center))
+            .map(Cluster::from_center)
             .collect::<Vec<Cluster>>();
 
+        let mut iterations = 0;
 
         // A map based in index to sender points to add in clusters (tasks)
         loop {
-            let (tx_final_clusters, rx_final_clusters) = mpsc::channel::<Cluster>();
-
-            let clusters_senders: Arc<Vec<mpsc::Sender<&Point>>> = clusters
-                .iter()
-                .map(|cluster| {
-                    let (sender_points, listen_points) = mpsc::channel::<&Point>();
-                    std::thread::spawn({
-                        let center = cluster.center.clone();
-                        let send_finish = tx_final_clusters.clone();
-                        move || {
-                            let mut points = Vec::with_capacity(data.len());
-                            while let Ok(point) = listen_points.recv() {
-                                points.push(point);
+            // Scoped, so that the threads can borrow `data`
+            let mut indexed_clusters = std::thread::scope(|scope| {
+                let (tx_final_clusters, rx_final_clusters) = mpsc::channel::<(usize, Cluster)>();
+
+                let clusters_senders: Arc<Vec<mpsc::Sender<&Point>>> = clusters
+                    .iter()
+                    .enumerate()
+                    .map(|(index, cluster)| {
+                        let (sender_points, listen_points) = mpsc::channel::<&Point>();
+                        scope.spawn({
+                            let center = cluster.center.clone();
+                            let send_finish = tx_final_clusters.clone();
+                            move || {
+                                let mut points = Vec::with_capacity(data.len());
+                                while let Ok(point) = listen_points.recv() {
+                                    points.push(point);
+                                }
+                                common::sort_in_input_order(&mut points);
+                                send_finish
+                                    .send((index, Cluster { center, points }))
+                                    .unwrap();
                             }
-                            send_finish.send(Cluster { center, points }).unwrap();
+                        });
+                        sender_points
+                    })
+                    .collect::<Vec<mpsc::Sender<&Point>>>()
+                    .into();
+
+                drop(tx_final_clusters);
+
+                let clusters_arc = Arc::new(clusters);
+
+                let max_threads = self.max_threads.min(data.len());
+                for mut index in 0..max_threads {
+                    let clusters_senders = clusters_senders.clone();
+                    let clusters = clusters_arc.clone();
+                    scope.spawn(move || {
+                        while index < data.len() {
+                            let point = data.get(index).unwrap();
+
+                            let ind_closest_cluster =
+                                common::get_closest_cluster_index(point, clusters.as_ref());
+                            clusters_senders
+                                .get(ind_closest_cluster)
+                                .unwrap()
+                                .send(point)
+                                .unwrap();
+                            index += max_threads;
                         }
                     });
-                    sender_points
-                })
-                .collect::<Vec<mpsc::Sender<&Point>>>()
-                .into();
-
-            drop(tx_final_clusters);
-
-            let clusters_arc = Arc::new(clusters);
-
-            let max_threads = self.max_threads.min(data.len());
-            for mut index in 0..max_threads {
-                let clusters_senders = clusters_senders.clone();
-                let clusters = clusters_arc.clone();
-                std::thread::spawn(move || {
-                    while index < data.len() {
-                        let point = data.get(index).unwrap();
-
-                        let ind_closest_cluster =
-                            common::get_closest_cluster_index(point, clusters.as_ref());
-                        clusters_senders
-                            .get(ind_closest_cluster)
-                            .unwrap()
-                            .send(point)
-                            .unwrap();
-                        index += max_threads;
-                    }
-                });
-            }
+                }
 
-            drop(clusters_senders);
+                drop(clusters_senders);
 
-            clusters = Vec::with_capacity(k as usize);
-            while let Ok(cluster) = rx_final_clusters.recv() {
-                clusters.push(cluster);
-            }
+                let mut indexed_clusters = Vec::with_capacity(k);
+                while let Ok(indexed_cluster) = rx_final_clusters.recv() {
+                    indexed_clusters.push(indexed_cluster);
+                }
+                indexed_clusters
+            });
+
+            // Clusters finish in any order, put them back in initial-center order
+            indexed_clusters.sort_unstable_by_key(|(index, _)| *index);
+            clusters = indexed_clusters
+                .into_iter()
+                .map(|(_, cluster)| cluster)
+                .collect();
+            iterations += 1;
 
             let new_centers: Vec<Point> = common::calculate_new_centers(&clusters);
+            let stop = progress.iteration(&clusters, &new_centers).is_break();
             let old_centers: Vec<_> = clusters.iter().map(|cluster| &cluster.center).collect();
 
-            if common::converged(new_centers.iter(), old_centers) {
-                return clusters;
+            if stop || common::converged(new_centers.iter(), old_centers) {
+                return Execution {
+                    clusters,
+                    iterations,
+                };
             }
 
-            clusters = new_centers
-                .into_iter()
-                .map(|center| Cluster::from_center(center))
-                .collect();
+            clusters = new_centers.into_iter().map(Cluster::from_center).collect();
         }
     }
 }
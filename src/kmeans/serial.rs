@@ -4,40 +4,43 @@ use crate::entities::{Cluster, Point};
 
 use super::common;
 
-use super::Kmeans;
+use super::{Execution, Kmeans, Observer};
 
 #[derive(Default)]
 pub struct KmeansSerialBuilder;
 
 impl Kmeans for KmeansSerialBuilder {
-    fn execute<'a>(
+    fn run_observed<'a>(
         &self,
-        data: &'static Vec<Point>,
-        _k: u8,
+        data: &'a [Point],
+        _k: usize,
         initial_centers: Vec<Point>,
-    ) -> Vec<Cluster<'a>> {
+        observer: Option<&mut dyn Observer>,
+    ) -> Execution<'a> {
+        let mut progress = common::Progress::new(data, &initial_centers, observer);
         let mut clusters = initial_centers
             .into_iter()
-            .map(|center| Cluster::from_center(center))
+            .map(Cluster::from_center)
             .collect::<Vec<Cluster>>();
 
-        let mut i: u64 = 1;
+        let mut iterations = 0;
         loop {
             clusters = common::assign_points(data, clusters);
 
-            i = i.saturating_add(1);
+            iterations += 1;
 
             let new_centers: Vec<Point> = common::calculate_new_centers(&clusters);
+            let stop = progress.iteration(&clusters, &new_centers).is_break();
             let old_centers: Vec<_> = clusters.iter().map(|cluster| &cluster.center).collect();
 
-            if common::converged(new_centers.iter(), old_centers) {
-                return clusters;
+            if stop || common::converged(new_centers.iter(), old_centers) {
+                return Execution {
+                    clusters,
+                    iterations,
+                };
             }
 
-            clusters = new_centers
-                .into_iter()
-                .map(|center| Cluster::from_center(center))
-                .collect();
+            clusters = new_centers.into_iter().map(Cluster::from_center).collect();
         }
     }
 }
@@ -1,102 +1,134 @@
 #![allow(dead_code)]
 
-use std::sync::Arc;
-
 use tokio::sync::mpsc;
 
 use crate::entities::{Cluster, Point};
 
-use super::{common, Kmeans};
+use super::{common, Execution, Kmeans, Observer};
 
-#[derive(Default)]
 pub struct KmeansTokioBuilder {
-    pub max_threads: usize,
+    max_threads: usize,
+    /// Shared by every run, so that they don't pay for starting it
+    runtime: tokio::runtime::Runtime,
+}
+
+impl KmeansTokioBuilder {
+    /// `max_threads` workers, or one per CPU when 0
+    pub fn new(max_threads: usize) -> Self {
+        Self {
+            max_threads,
+            runtime: common::runtime(max_threads),
+        }
+    }
 }
 
 impl Kmeans for KmeansTokioBuilder {
-    fn execute<'a>(
+    fn run_observed<'a>(
         &self,
-        data: &'static Vec<Point>,
-        k: u8,
+        data: &'a [Point],
+        k: usize,
         initial_centers: Vec<Point>,
-    ) -> Vec<Cluster<'a>> {
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        rt.block_on(async move {
+        observer: Option<&mut dyn Observer>,
+    ) -> Execution<'a> {
+        self.runtime.block_on(async move {
+            let mut progress = common::Progress::new(data, &initial_centers, observer);
             let mut clusters = initial_centers
                 .into_iter()
-                .map(|center| Cluster::from_center(center))
+                .map(Cluster::from_center)
                 .collect::<Vec<Cluster>>();
 
-            eprintln!("initial centers: {:?}", clusters);
+            let mut iterations = 0;
 
             // A map based in index to sender points to add in clusters (tasks)
             loop {
                 let (tx_final_clusters, mut rx_final_clusters) =
-                    tokio::sync::mpsc::channel::<Cluster>(k.into());
+                    tokio::sync::mpsc::channel::<(usize, Vec<usize>)>(k);
 
-                let clusters_senders: Arc<Vec<mpsc::Sender<&Point>>> = clusters
-                    .iter()
-                    .map(|cluster| {
-                        let (sender_points, mut listen_points) = mpsc::channel::<&Point>(500);
+                let clusters_senders: Vec<mpsc::Sender<usize>> = (0..clusters.len())
+                    .map(|index| {
+                        let (sender_points, mut listen_points) = mpsc::channel::<usize>(500);
                         tokio::task::spawn({
-                            let center = cluster.center.clone();
                             let send_finish = tx_final_clusters.clone();
+                            let capacity = data.len();
                             async move {
-                                let mut points = Vec::with_capacity(data.len());
+                                let mut indexes = Vec::with_capacity(capacity);
                                 while let Some(point) = listen_points.recv().await {
-                                    points.push(point);
+                                    indexes.push(point);
                                 }
-                                send_finish.send(Cluster { center, points }).await.unwrap();
+                                indexes.sort_unstable();
+                                send_finish.send((index, indexes)).await.unwrap();
                             }
                         });
                         sender_points
                     })
-                    .collect::<Vec<mpsc::Sender<&Point>>>()
-                    .into();
+                    .collect();
 
                 drop(tx_final_clusters);
 
-                let clusters_arc = Arc::new(clusters);
+                let centers: Vec<Point> =
+                    clusters.into_iter().map(|cluster| cluster.center).collect();
 
+                // Spawned tasks can't borrow `data`, so the points are read by scoped
+                // threads, each blocking on the runtime
+                let handle = self.runtime.handle();
                 let max_threads = self.max_threads.min(data.len());
-                for mut index in 0..max_threads {
-                    let clusters_senders = clusters_senders.clone();
-                    let clusters = clusters_arc.clone();
-                    tokio::task::spawn(async move {
-                        while index < data.len() {
-                            let point = data.get(index).unwrap();
-
-                            let ind_closest_cluster =
-                                common::get_closest_cluster_index(point, clusters.as_ref());
-                            clusters_senders
-                                .get(ind_closest_cluster)
-                                .unwrap()
-                                .send(point)
-                                .await
-                                .unwrap();
-                            index += max_threads;
-                        }
-                    });
-                }
+                std::thread::scope(|scope| {
+                    for mut index in 0..max_threads {
+                        let clusters_senders = &clusters_senders;
+                        let centers = &centers;
+                        scope.spawn(move || {
+                            handle.block_on(async move {
+                                while index < data.len() {
+                                    let point = data.get(index).unwrap();
+
+                                    let ind_closest_cluster =
+                                        common::get_closest_cluster_index_based_in_centroids(
+                                            point,
+                                            centers.iter(),
+                                        );
+                                    clusters_senders
+                                        .get(ind_closest_cluster)
+                                        .unwrap()
+                                        .send(index)
+                                        .await
+                                        .unwrap();
+                                    index += max_threads;
+                                }
+                            })
+                        });
+                    }
+                });
 
                 drop(clusters_senders);
 
-                clusters = Vec::with_capacity(k as usize);
-                while let Some(cluster) = rx_final_clusters.recv().await {
-                    clusters.push(cluster);
+                // Clusters finish in any order, put them back in initial-center order
+                let mut indexed_clusters = Vec::with_capacity(k);
+                while let Some(indexed_cluster) = rx_final_clusters.recv().await {
+                    indexed_clusters.push(indexed_cluster);
                 }
+                indexed_clusters.sort_unstable_by_key(|(index, _)| *index);
+                clusters = indexed_clusters
+                    .into_iter()
+                    .zip(centers.iter())
+                    .map(|((_, indexes), center)| Cluster {
+                        center: center.clone(),
+                        points: indexes.into_iter().map(|index| &data[index]).collect(),
+                    })
+                    .collect();
+                iterations += 1;
 
                 let new_centers: Vec<Point> = common::calculate_new_centers(&clusters);
+                let stop = progress.iteration(&clusters, &new_centers).is_break();
                 let old_centers: Vec<_> = clusters.iter().map(|cluster| &cluster.center).collect();
 
-                if common::converged(new_centers.iter(), old_centers) {
-                    return clusters;
+                if stop || common::converged(new_centers.iter(), old_centers) {
+                    return Execution {
+                        clusters,
+                        iterations,
+                    };
                 }
 
-                clusters = new_centers
-                    .into_iter()
-                    .map(|center| Cluster::from_center(center))
-                    .collect();
+                clusters = new_centers.into_iter().map(Cluster::from_center).collect();
             }
         })
     }
@@ -1,127 +1,972 @@
+use std::cell::Cell;
 use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::num::NonZeroUsize;
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
 
 use itertools::Itertools;
+use rayon::prelude::*;
 
 use std::sync::OnceLock;
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use kmeans::{parallel_2::KmeansParallelBuilder2, tokio::KmeansTokioBuilder, Kmeans};
 
 use crate::{
-    entities::Point,
+    color::ColorSpace,
+    entities::{Cluster, Point},
+    format::Format,
+    image::Image,
+    input::{
+        BenchArgs, ClusterArgs, Command, InitArgs, InputArgs, IoArgs, Mode, OnlineArgs,
+        PredictArgs, StreamArgs,
+    },
+    json::Assignment,
     kmeans::{
-        parallel::KmeansParallelBuilder, parallel_3::KmeansParallelStdBuilder,
-        parallel_mutex::KmeansParallelMutex, serial::KmeansSerialBuilder,
+        dedup::Deduplicated, parallel::KmeansParallelBuilder, parallel_3::KmeansParallelStdBuilder,
+        parallel_mutex::KmeansParallelMutex, serial::KmeansSerialBuilder, Observer,
     },
+    model::{Model, Preprocessing},
+    palette::{PaletteFormat, Swatch},
+    rows::{Column, InputError, InputRow, RowErrors},
+    slic::{Slic, SlicOutput},
+    stream::{Chunks, CsvChunks},
+    trace::Trace,
 };
 
+mod arrow;
+mod bench;
+mod color;
 mod entities;
+mod format;
+mod image;
 mod input;
+mod json;
 mod kmeans;
+mod metrics;
+mod model;
+mod npy;
+mod online;
+mod palette;
+mod rows;
+mod slic;
+mod spatial;
+mod stream;
+mod trace;
+
+/// Number of assignment passes of SLIC, 10 is enough for most images
+const SLIC_ITERATIONS: usize = 10;
+
+/// Points to cluster and the optional ground-truth class of each one
+type Dataset = (&'static [Point], Vec<Option<String>>);
 
 fn input_data(data: Vec<Point>) -> &'static Vec<Point> {
     static COMPUTATION: OnceLock<Vec<Point>> = OnceLock::new();
     COMPUTATION.get_or_init(|| data)
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let matches = input::Args::parse();
+fn read_image(input: &InputArgs, format: Format) -> Result<Vec<InputRow>, Box<dyn Error>> {
+    if input.ground_truth {
+        return Err("a ground-truth column requires CSV input".into());
+    }
+    let image = match input.input_file.as_deref() {
+        Some(path) => Image::read(File::open(path)?, format)?,
+        None => Image::read(std::io::stdin().lock(), format)?,
+    };
+    if image.pixels.is_empty() {
+        return Err(InputError::Empty.into());
+    }
+    Ok(image
+        .pixels()
+        .map(|(x, y, rgb)| (format!("{x}:{y}"), None, rgb.map(f64::from).to_vec(), 1.0))
+        .collect())
+}
+
+/// Rows of a binary array file, labelled by their index. Rows with a non-finite value
+/// are invalid
+fn read_array(
+    input: &InputArgs,
+    format: Format,
+    errors: &mut RowErrors,
+) -> Result<Vec<InputRow>, Box<dyn Error>> {
+    if input.ground_truth {
+        return Err("a ground-truth column requires CSV or NDJSON input".into());
+    }
+    let reader: Box<dyn std::io::Read> = match input.input_file.as_deref() {
+        Some(path) => Box::new(std::io::BufReader::new(File::open(path)?)),
+        None => Box::new(std::io::stdin().lock()),
+    };
+    let rows = match format {
+        Format::Fvecs => npy::read_fvecs(reader)?,
+        Format::Bvecs => npy::read_bvecs(reader)?,
+        _ => npy::read_npy(reader)?,
+    };
+    let dimension = rows.first().map_or(0, Vec::len);
+    if dimension == 0 {
+        return Err(InputError::Empty.into());
+    }
+    if let Some(index) = rows.iter().position(|row| row.len() != dimension) {
+        return Err(format!(
+            "vector {index} has {} values, expected {dimension}",
+            rows[index].len()
+        )
+        .into());
+    }
+    let mut valid = Vec::with_capacity(rows.len());
+    for (index, values) in rows.into_iter().enumerate() {
+        let row = match values.iter().all(|value| value.is_finite()) {
+            true => Ok((index.to_string(), None, values, 1.0)),
+            false => Err(InputError::Parse {
+                line: index as u64 + 1,
+                content: values.iter().map(f64::to_string).join(" "),
+                message: "non-finite value".to_string(),
+            }),
+        };
+        if let Some(row) = errors.handle(row)? {
+            valid.push(row);
+        }
+    }
+    if valid.is_empty() {
+        return Err(InputError::Empty.into());
+    }
+    Ok(valid)
+}
+
+/// Rows of an Arrow IPC file, which must be seekable: STDIN is read in memory first
+fn read_arrow(input: &InputArgs, errors: &mut RowErrors) -> Result<Vec<InputRow>, Box<dyn Error>> {
+    if input.ground_truth {
+        return Err("a ground-truth column requires CSV or NDJSON input".into());
+    }
+    let label = &input.label_column;
+    let features = input.feature_columns.as_deref();
+    let weight = input.weight_column.as_ref();
+    match input.input_file.as_deref() {
+        Some(path) => arrow::read_table(File::open(path)?, label, features, weight, errors),
+        None => {
+            let mut bytes = Vec::new();
+            std::io::Read::read_to_end(&mut std::io::stdin().lock(), &mut bytes)?;
+            arrow::read_table(std::io::Cursor::new(bytes), label, features, weight, errors)
+        }
+    }
+}
+
+/// Draws `X:Y R G B` rows into an image as large as the biggest coordinates
+fn rows_to_image(rows: impl IntoIterator<Item = Vec<String>>) -> Result<Image, Box<dyn Error>> {
+    let pixels = rows
+        .into_iter()
+        .map(|row| {
+            let coordinates = spatial::parse_coordinates(&row[0]);
+            let rgb = row[1..]
+                .iter()
+                .map(|n| n.parse::<u8>().ok())
+                .collect::<Option<Vec<_>>>();
+            match (coordinates, rgb.as_deref()) {
+                (Some((x, y)), Some(&[r, g, b])) => Ok((x as usize, y as usize, [r, g, b])),
+                _ => Err(format!(
+                    "row `{}` is not an `X:Y R G B` pixel",
+                    row.join(" ")
+                )),
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let width = pixels.iter().map(|&(x, _, _)| x + 1).max().unwrap_or(0);
+    let height = pixels.iter().map(|&(_, y, _)| y + 1).max().unwrap_or(0);
+    let mut image = Image::new(width, height)?;
+    for (x, y, rgb) in pixels {
+        image.pixels[y * width + x] = rgb;
+    }
+    Ok(image)
+}
+
+fn write_rows(
+    output_file: Option<&Path>,
+    format: Format,
+    rows: impl IntoIterator<Item = Vec<String>>,
+) -> Result<(), Box<dyn Error>> {
+    if matches!(format, Format::Fvecs | Format::Bvecs) {
+        return Err(format!("{format:?} is an input format only").into());
+    }
+    if format.is_image() {
+        let image = rows_to_image(rows)?;
+        return match output_file {
+            Some(path) => image.write(BufWriter::new(File::create(path)?), format),
+            None => image.write(std::io::stdout().lock(), format),
+        };
+    }
+
+    let mut writer = csv_writer(output_file)?;
+    for row in rows {
+        writer.write_record(row)?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// `output_file`, or STDOUT
+fn output_writer(output_file: Option<&Path>) -> Result<Box<dyn Write>, Box<dyn Error>> {
+    Ok(match output_file {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(std::io::stdout()),
+    })
+}
+
+/// Space delimited writer to `output_file`, or STDOUT
+fn csv_writer(output_file: Option<&Path>) -> Result<csv::Writer<Box<dyn Write>>, Box<dyn Error>> {
+    Ok(csv::WriterBuilder::new()
+        .has_headers(false)
+        .delimiter(b' ')
+        .from_writer(output_writer(output_file)?))
+}
+
+fn rgb_row(label: &str, rgb: [f64; 3]) -> Vec<String> {
+    let [x, y, z] = rgb.map(|n| n.round().clamp(0.0, 255.0) as u8);
+    vec![
+        label.to_string(),
+        x.to_string(),
+        y.to_string(),
+        z.to_string(),
+    ]
+}
+
+/// Label followed by the values of an entry, as an RGB color when `color` is set
+fn values_row(label: &str, values: &[f64], color: bool) -> Vec<String> {
+    match (color, values) {
+        (true, &[r, g, b, ..]) => rgb_row(label, [r, g, b]),
+        _ => std::iter::once(label.to_string())
+            .chain(values.iter().map(f64::to_string))
+            .collect(),
+    }
+}
+
+/// Label and cluster index of a point, followed by the optional distance and original values columns
+fn assignment_row(
+    point: &Point,
+    cluster: usize,
+    center: &Point,
+    io: &IoArgs,
+    preprocessing: &Preprocessing,
+) -> Vec<String> {
+    let mut row = vec![
+        point.get_label().unwrap_or("--").to_string(),
+        cluster.to_string(),
+    ];
+    if io.with_distance {
+        row.push(point.euclidean_distance(center).sqrt().to_string());
+    }
+    if io.with_values {
+        let values = preprocessing.restore(point.get_data());
+        row.extend(
+            values_row("", &values, io.input.is_color())
+                .into_iter()
+                .skip(1),
+        );
+    }
+    row
+}
 
-    let mut csv_builder = csv::ReaderBuilder::new();
-    let csv_builder = csv_builder.has_headers(false).delimiter(b' ');
+/// Cluster of `point` with its distance and values, following the same flags as [`assignment_row`]
+fn json_assignment<'a>(
+    point: &'a Point,
+    cluster: usize,
+    center: &Point,
+    io: &IoArgs,
+    preprocessing: &Preprocessing,
+) -> Assignment<'a> {
+    let (distance, values) = match io.replace_entry {
+        true => (None, Some(preprocessing.restore(center.get_data()))),
+        false => (
+            io.with_distance
+                .then(|| point.euclidean_distance(center).sqrt()),
+            io.with_values
+                .then(|| preprocessing.restore(point.get_data())),
+        ),
+    };
+    Assignment {
+        label: point.get_label().unwrap_or("--"),
+        cluster,
+        distance,
+        values,
+    }
+}
 
-    let input_values: Vec<(String, u32, u32, u32)> = match matches.input_file {
+/// Writes the assignments of `points` to `centers` as NDJSON lines, or as a JSON document
+/// that also holds the centers and the run statistics
+fn write_json<'a>(
+    io: &IoArgs,
+    preprocessing: &Preprocessing,
+    centers: &[Point],
+    points: impl Iterator<Item = (&'a Point, usize)>,
+    iterations: usize,
+    elapsed: Duration,
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = BufWriter::new(output_writer(io.output_file.as_deref())?);
+    let assign = |(point, cluster): (&'a Point, usize)| {
+        json_assignment(point, cluster, &centers[cluster], io, preprocessing)
+    };
+    if io.output_format() == Format::Ndjson {
+        return json::write_ndjson(writer, points.map(assign));
+    }
+
+    let mut sizes = vec![0; centers.len()];
+    let mut inertia = 0.0;
+    let assignments: Vec<Assignment> = points
+        .inspect(|&(point, cluster)| {
+            sizes[cluster] += 1;
+            inertia += point.get_weight() * point.euclidean_distance(&centers[cluster]);
+        })
+        .map(assign)
+        .collect();
+    let document = json::Document {
+        centroids: centers
+            .iter()
+            .map(|center| preprocessing.restore(center.get_data()))
+            .collect(),
+        sizes,
+        stats: json::Stats {
+            points: assignments.len(),
+            iterations,
+            inertia,
+            elapsed_ms: elapsed.as_secs_f64() * 1000.0,
+        },
+        assignments,
+    };
+    serde_json::to_writer(&mut writer, &document)?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes the cluster of every entry, in input order, as an int64 NumPy array
+fn write_labels(io: &IoArgs, labels: &[usize]) -> Result<(), Box<dyn Error>> {
+    let labels: Vec<i64> = labels.iter().map(|&label| label as i64).collect();
+    npy::write_i64(
+        BufWriter::new(output_writer(io.output_file.as_deref())?),
+        &labels,
+    )
+}
+
+/// Writes the label and cluster of every entry, in input order, as an Arrow IPC file.
+/// The label column keeps the name given to `--label-column`
+fn write_arrow<'a>(
+    io: &IoArgs,
+    centers: &[Point],
+    assignments: impl IntoIterator<Item = (&'a Point, usize)>,
+) -> Result<(), Box<dyn Error>> {
+    let label_name = match &io.input.label_column {
+        Column::Name(name) => name.as_str(),
+        Column::Index(_) => "label",
+    };
+    let (points, clusters): (Vec<&Point>, Vec<usize>) = assignments.into_iter().unzip();
+    let distances = (!io.replace_entry && io.with_distance).then(|| {
+        points
+            .iter()
+            .zip(&clusters)
+            .map(|(point, &cluster)| point.euclidean_distance(&centers[cluster]).sqrt())
+            .collect()
+    });
+    arrow::write_assignments(
+        BufWriter::new(output_writer(io.output_file.as_deref())?),
+        label_name,
+        points
+            .iter()
+            .map(|point| point.get_label().unwrap_or("--"))
+            .collect(),
+        clusters.iter().map(|&cluster| cluster as u32).collect(),
+        distances,
+    )
+}
+
+/// Writes `centers` to `--centroids-out`, when given
+fn write_centroids<'a>(
+    io: &IoArgs,
+    preprocessing: &Preprocessing,
+    centers: impl IntoIterator<Item = &'a Point>,
+) -> Result<(), Box<dyn Error>> {
+    let Some(path) = &io.centroids_out else {
+        return Ok(());
+    };
+    let centers: Vec<Vec<f64>> = centers
+        .into_iter()
+        .map(|center| preprocessing.restore(center.get_data()))
+        .collect();
+    npy::write_f64(BufWriter::new(File::create(path)?), &centers)
+}
+
+/// Writes the palette of `centers`, each with the size of its cluster
+fn write_palette<'a>(
+    path: &Path,
+    centers: impl IntoIterator<Item = (&'a Point, usize)>,
+    color_space: ColorSpace,
+) -> Result<(), Box<dyn Error>> {
+    let format = PaletteFormat::from_path(path)?;
+    let swatches: Vec<Swatch> = centers
+        .into_iter()
+        .map(|(center, count)| Swatch {
+            rgb: color_space.to_rgb8(center.get_data()),
+            count,
+        })
+        .collect();
+    palette::write_palette(path, format, &swatches)
+}
+
+/// Color spaces other than RGB convert the three values of a color, which arbitrary
+/// features are not
+fn check_color_space(input: &InputArgs, color_space: ColorSpace) -> Result<(), Box<dyn Error>> {
+    if color_space != ColorSpace::Rgb && !input.is_color() {
+        return Err("a color space other than RGB needs RGB input, not arbitrary features".into());
+    }
+    Ok(())
+}
+
+/// Fails early when `--palette-out` is given but can't be written
+fn check_palette(io: &IoArgs) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = &io.palette_out {
+        PaletteFormat::from_path(path)?;
+        if !io.input.is_color() {
+            return Err("a palette needs RGB input, not feature columns".into());
+        }
+    }
+    Ok(())
+}
+
+fn cluster_sizes<'a>(clusters: &'a [Cluster]) -> impl Iterator<Item = (&'a Point, usize)> {
+    clusters
+        .iter()
+        .map(|cluster| (&cluster.center, cluster.points.len()))
+}
+
+fn read_input(input: &InputArgs) -> Result<Vec<InputRow>, Box<dyn Error>> {
+    let layout = input.layout();
+    let csv_builder = layout.reader_builder();
+    let mut errors = RowErrors::new(input.on_error);
+
+    let input_format = input.input_format();
+    if !matches!(input_format, Format::Csv | Format::Arrow)
+        && (input.feature_columns.is_some() || input.weight_column.is_some())
+    {
+        return Err("feature and weight columns require CSV or Arrow input".into());
+    }
+    if input_format != Format::Csv && input.class_column.is_some() {
+        return Err("a class column requires CSV input".into());
+    }
+    let input_values = match &input.input_file {
+        _ if input_format.is_image() => read_image(input, input_format)?,
+        _ if input_format.is_array() => read_array(input, input_format, &mut errors)?,
+        _ if input_format == Format::Arrow => read_arrow(input, &mut errors)?,
+        _ if input_format == Format::Json => {
+            return Err("JSON documents are only written, read points as NDJSON".into())
+        }
+        Some(path) if input_format == Format::Ndjson => {
+            let reader = std::io::BufReader::new(File::open(path)?);
+            json::read_points(reader, input.ground_truth, &mut errors)?
+        }
+        None if input_format == Format::Ndjson => {
+            json::read_points(std::io::stdin().lock(), input.ground_truth, &mut errors)?
+        }
         Some(path) => {
             let mut reader = csv_builder.from_path(path)?;
-            reader
-                .deserialize()
-                .into_iter()
-                .filter_map(Result::unwrap)
-                .collect()
+            rows::read_rows(&mut reader, &layout, &mut errors)?
         }
         None => {
             let mut reader = csv_builder.from_reader(std::io::stdin());
-            reader
-                .deserialize()
-                .into_iter()
-                .filter_map(Result::unwrap)
-                .collect()
+            rows::read_rows(&mut reader, &layout, &mut errors)?
         }
     };
+    rows::report_skipped(errors.skipped());
+    Ok(input_values)
+}
 
-    // Kmeans
-    let k = matches.k;
-    let values = input_values
+/// Features of every input entry, with their ground-truth class when present
+fn load_points(
+    input: &InputArgs,
+    preprocessing: &Preprocessing,
+) -> Result<Dataset, Box<dyn Error>> {
+    let (values, truth): (Vec<Point>, Vec<Option<String>>) = read_input(input)?
         .into_iter()
-        .map(|(label, x, y, z)| Point::from([x, y, z]).with_label(&label))
-        .collect::<Vec<Point>>();
-    let values = input_data(values);
+        .map(|(label, class, values, weight)| {
+            let values = preprocessing.features(&label, &values)?;
+            let point = Point::from_vec(values)
+                .with_label(&label)
+                .with_weight(weight);
+            Ok((point, class))
+        })
+        .collect::<Result<Vec<_>, String>>()?
+        .into_iter()
+        .unzip();
+    Ok((input_data(values), truth))
+}
 
-    let initial_centers = if matches.random_initial {
-        kmeans::common::get_n_random_points(values, k as usize)
+fn print_metrics(truth: Vec<Option<String>>, predicted: &[usize]) {
+    let truth: Vec<String> = truth.into_iter().flatten().collect();
+    eprintln!("{}", metrics::ExternalMetrics::compute(&truth, predicted));
+}
+
+/// Writes every point of `clusters`, replaced by its center or with its cluster index
+fn write_clusters(
+    io: &IoArgs,
+    clusters: &[Cluster],
+    preprocessing: &Preprocessing,
+    iterations: usize,
+    elapsed: Duration,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = &io.palette_out {
+        write_palette(path, cluster_sizes(clusters), preprocessing.color_space)?;
+    }
+    write_centroids(io, preprocessing, clusters.iter().map(|el| &el.center))?;
+
+    match io.output_format() {
+        Format::Npy => {
+            let labels: Vec<usize> = kmeans::common::assignments_in_input_order(clusters)
+                .iter()
+                .map(|&(_, index)| index)
+                .collect();
+            return write_labels(io, &labels);
+        }
+        Format::Arrow => {
+            let centers: Vec<Point> = clusters.iter().map(|el| el.center.clone()).collect();
+            let assignments = kmeans::common::assignments_in_input_order(clusters);
+            return write_arrow(io, &centers, assignments);
+        }
+        _ => {}
+    }
+    if io.output_format().is_json() {
+        let centers: Vec<Point> = clusters.iter().map(|el| el.center.clone()).collect();
+        let points = clusters
+            .iter()
+            .enumerate()
+            .flat_map(|(index, el)| el.points.iter().map(move |&p| (p, index)));
+        return write_json(io, preprocessing, &centers, points, iterations, elapsed);
+    }
+
+    let color = io.input.is_color();
+    let output_values: Box<dyn Iterator<Item = Vec<String>>> = if io.replace_entry {
+        Box::new(clusters.iter().flat_map(move |el| {
+            let center = preprocessing.restore(el.center.get_data());
+            el.points
+                .iter()
+                .map(move |p| values_row(p.get_label().unwrap_or("--"), &center, color))
+        }))
     } else {
-        values
+        Box::new(clusters.iter().enumerate().flat_map(move |(index, el)| {
+            el.points
+                .iter()
+                .map(move |p| assignment_row(p, index, &el.center, io, preprocessing))
+        }))
+    };
+
+    write_rows(io.output_file.as_deref(), io.output_format(), output_values)
+}
+
+fn run_slic(
+    cluster_args: &ClusterArgs,
+    io: &IoArgs,
+    preprocessing: &Preprocessing,
+    values: &[Point],
+    truth: Vec<Option<String>>,
+) -> Result<(), Box<dyn Error>> {
+    let color_space = preprocessing.color_space;
+    let coordinates = spatial::pixel_coordinates(values)?;
+
+    let slic = Slic {
+        superpixels: cluster_args.init.k,
+        compactness: cluster_args.compactness,
+        iterations: SLIC_ITERATIONS,
+    };
+    let start = Instant::now();
+    let segments = slic.segment(values, &coordinates)?;
+    let elapsed = start.elapsed();
+
+    if io.input.ground_truth {
+        print_metrics(truth, &segments);
+    }
+
+    let segment_count = segments.iter().max().map_or(0, |max| max + 1);
+    let mut superpixels: Vec<Cluster> = (0..segment_count)
+        .map(|_| Cluster::from_center(values[0].clone()))
+        .collect();
+    for (point, &segment) in values.iter().zip(&segments) {
+        superpixels[segment].points.push(point);
+    }
+    for superpixel in &mut superpixels {
+        superpixel.center = superpixel.calculate_center_point();
+    }
+    if let Some(path) = &io.palette_out {
+        write_palette(path, cluster_sizes(&superpixels), color_space)?;
+    }
+    let means: Vec<Point> = superpixels
+        .iter()
+        .map(|superpixel| superpixel.center.clone())
+        .collect();
+    write_centroids(io, preprocessing, &means)?;
+    if io.output_format() == Format::Npy {
+        return write_labels(io, &segments);
+    }
+    if io.output_format() == Format::Arrow {
+        return write_arrow(io, &means, values.iter().zip(segments.iter().copied()));
+    }
+    if io.output_format().is_json() {
+        let points = values.iter().zip(segments.iter().copied());
+        return write_json(io, preprocessing, &means, points, SLIC_ITERATIONS, elapsed);
+    }
+    let mean_colors: Vec<[f64; 3]> = means
+        .iter()
+        .map(|mean| {
+            let mean = mean.get_data();
+            color_space.to_rgb([mean[0], mean[1], mean[2]])
+        })
+        .collect();
+
+    let output_values = values.iter().zip(&segments).map(|(point, &segment)| {
+        let label = point.get_label().unwrap_or("--");
+        if !io.replace_entry {
+            return assignment_row(point, segment, &means[segment], io, preprocessing);
+        }
+        match cluster_args.slic_output {
+            SlicOutput::MeanColor => rgb_row(label, mean_colors[segment]),
+            SlicOutput::Id => rgb_row(
+                label,
+                [16, 8, 0].map(|shift| ((segment >> shift) & 0xff) as f64),
+            ),
+        }
+    });
+
+    write_rows(io.output_file.as_deref(), io.output_format(), output_values)
+}
+
+/// The first `k` distinct points, or `k` random ones with `--random-initial`
+fn initial_centers(values: &[Point], init: &InitArgs) -> Vec<Point> {
+    match init.rng() {
+        Some(mut rng) => kmeans::common::get_n_random_points(values, init.k, &mut rng),
+        None => values
             .iter()
             .unique_by(|p| p.get_values())
-            .take(k as usize)
+            .take(init.k)
             .cloned()
-            .collect()
+            .collect(),
+    }
+}
+
+/// `--threads`, or the available parallelism
+fn thread_count(threads: Option<NonZeroUsize>) -> usize {
+    threads
+        .or_else(|| std::thread::available_parallelism().ok())
+        .map_or(1, NonZeroUsize::get)
+}
+
+fn runner(mode: Mode, max_threads: usize) -> Box<dyn Kmeans> {
+    match mode {
+        Mode::S => Box::new(KmeansSerialBuilder),
+        Mode::Par => Box::new(KmeansParallelStdBuilder { max_threads }),
+        Mode::Mutex => Box::new(KmeansParallelMutex { max_threads }),
+        Mode::Tokio => Box::new(KmeansTokioBuilder::new(max_threads)),
+        Mode::Ray => Box::new(KmeansParallelBuilder2::new(max_threads)),
+        Mode::Ray2 => Box::new(KmeansParallelBuilder::new(max_threads)),
+    }
+}
+
+/// Clusters the input and writes the result. The fitted model is saved to `model_out` when given
+fn fit(
+    cluster_args: &ClusterArgs,
+    io: &IoArgs,
+    model_out: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    check_palette(io)?;
+    if cluster_args.slic && model_out.is_some() {
+        return Err("SLIC superpixels can't be saved as a model".into());
+    }
+    if cluster_args.slic && !io.input.is_color() {
+        return Err("SLIC superpixels need RGB input, not feature columns".into());
+    }
+    let preprocessing = cluster_args.preprocessing.preprocessing();
+    check_color_space(&io.input, preprocessing.color_space)?;
+
+    let (values, truth) = load_points(&io.input, &preprocessing)?;
+
+    if cluster_args.slic {
+        return run_slic(cluster_args, io, &preprocessing, values, truth);
+    }
+
+    // Kmeans
+    let k = cluster_args.init.k;
+    let initial_centers = initial_centers(values, &cluster_args.init);
+    let start = Instant::now();
+    let mode = cluster_args.mode.ok_or("missing --mode")?;
+    let mut runner = runner(mode, thread_count(cluster_args.threads));
+    if cluster_args.dedup {
+        runner = Box::new(Deduplicated { inner: runner });
+    }
+    let mut trace = cluster_args.trace_out.as_ref().map(|_| Trace::default());
+    let observer = trace.as_mut().map(|trace| trace as &mut dyn Observer);
+    let execution = runner.run_observed(values, k, initial_centers, observer);
+    let elapsed = start.elapsed();
+    let clusters = execution.clusters;
+
+    if io.input.ground_truth {
+        print_metrics(truth, &kmeans::common::get_assignments(values, &clusters));
+    }
+
+    if let Some(path) = model_out {
+        Model::new(preprocessing, &clusters).save(path)?;
+    }
+    if let (Some(trace), Some(path)) = (trace, &cluster_args.trace_out) {
+        trace.save(path)?;
+    }
+
+    write_clusters(io, &clusters, &preprocessing, execution.iterations, elapsed)
+}
+
+/// Assigns the input to the centers of a saved model
+fn predict(args: &PredictArgs) -> Result<(), Box<dyn Error>> {
+    let io = &args.io;
+    check_palette(io)?;
+
+    let model = Model::load(&args.model)?;
+    check_color_space(&io.input, model.preprocessing.color_space)?;
+    let (values, truth) = load_points(&io.input, &model.preprocessing)?;
+
+    let mut clusters = model.clusters();
+    let dimension = clusters[0].center.dimension();
+    if let Some(point) = values.iter().find(|point| point.dimension() != dimension) {
+        return Err(format!(
+            "entry {} has {} features, the model expects {dimension}",
+            point.get_label().unwrap_or("--"),
+            point.dimension()
+        )
+        .into());
+    }
+
+    let start = Instant::now();
+    let assignments = kmeans::common::get_assignments(values, &clusters);
+    let elapsed = start.elapsed();
+    for (point, &index) in values.iter().zip(&assignments) {
+        clusters[index].points.push(point);
+    }
+
+    if io.input.ground_truth {
+        print_metrics(truth, &assignments);
+    }
+
+    write_clusters(io, &clusters, &model.preprocessing, 0, elapsed)
+}
+
+/// Times every mode on the same input and initial centers
+fn run_bench(args: &BenchArgs) -> Result<(), Box<dyn Error>> {
+    let preprocessing = args.preprocessing.preprocessing();
+    check_color_space(&args.input, preprocessing.color_space)?;
+    let (values, _) = load_points(&args.input, &preprocessing)?;
+    let initial_centers = initial_centers(values, &args.init);
+
+    let threads = thread_count(args.threads);
+    let runners = Mode::value_variants()
+        .iter()
+        .map(|&mode| (mode, runner(mode, threads)));
+    let measurements = bench::run(
+        runners,
+        values,
+        args.init.k,
+        &initial_centers,
+        args.repetitions,
+    );
+
+    match &args.output_file {
+        Some(path) => bench::write_report(
+            BufWriter::new(File::create(path)?),
+            args.report,
+            &measurements,
+        ),
+        None => bench::write_report(std::io::stdout().lock(), args.report, &measurements),
+    }
+}
+
+/// Clusters a CSV file with chunked passes over it, then streams the assignments out
+fn run_stream(args: &StreamArgs) -> Result<(), Box<dyn Error>> {
+    let io = &args.io;
+    let Some(path) = &io.input.input_file else {
+        return Err("streaming needs an input file, it is read once per iteration".into());
     };
+    if io.input.ground_truth {
+        return Err("ground-truth metrics are not available when streaming".into());
+    }
+    if io.input.input_format() != Format::Csv {
+        return Err("streaming only reads CSV".into());
+    }
+    if !matches!(io.output_format(), Format::Csv | Format::Ndjson) {
+        return Err("streaming only writes CSV or NDJSON".into());
+    }
+    check_palette(io)?;
+    let preprocessing = args.preprocessing.preprocessing();
+    check_color_space(&io.input, preprocessing.color_space)?;
 
-    let kmeans_runner: Box<dyn Kmeans> = match matches.mode {
-        input::Mode::S => Box::new(KmeansSerialBuilder::default()),
-        input::Mode::Par => Box::new(KmeansParallelStdBuilder { max_threads: 8 }),
-        input::Mode::Mutex => Box::new(KmeansParallelMutex { max_threads: 8 }),
-        input::Mode::Tokio => Box::new(KmeansTokioBuilder { max_threads: 8 }),
-        input::Mode::Ray => Box::new(KmeansParallelBuilder2::default()),
-        input::Mode::Ray2 => Box::new(KmeansParallelBuilder::default()),
+    let source = CsvChunks {
+        path: path.clone(),
+        chunk_size: args.chunk_size,
+        layout: io.input.layout(),
+        preprocessing,
+        on_error: io.input.on_error,
+        skipped: Cell::new(None),
+        read_until: Cell::new(0),
     };
 
-    let clusters = kmeans_runner.execute(&values, k as u8, initial_centers);
+    let initial_centers = match args.init.rng() {
+        Some(mut rng) => stream::sample_points(&source, args.init.k, &mut rng)?,
+        None => stream::first_distinct_points(&source, args.init.k)?,
+    };
+    let fitted = stream::fit(&source, initial_centers)?;
+    eprintln!("iterations: {}", fitted.iterations);
 
-    let output_values = if matches.replace_entry {
-        clusters
-            .iter()
-            .flat_map(|el| {
-                el.points
-                    .iter()
-                    .map(|p| el.center.clone().with_label(p.get_label().unwrap_or("--")))
-            })
+    if let Some(palette) = &io.palette_out {
+        write_palette(
+            palette,
+            fitted.centers.iter().zip(fitted.counts.iter().copied()),
+            preprocessing.color_space,
+        )?;
+    }
+    write_centroids(io, &preprocessing, &fitted.centers)?;
+
+    let color = io.input.is_color();
+    let centers: Vec<Vec<f64>> = fitted
+        .centers
+        .iter()
+        .map(|center| preprocessing.restore(center.get_data()))
+        .collect();
+
+    let assign = |chunk: &[Point]| -> Vec<usize> {
+        chunk
+            .par_iter()
             .map(|point| {
-                let label = point.get_label().unwrap_or("--");
-                let [x, y, z] = point.get_data().map(|n| n.max(0).min(255) as u8);
-
-                vec![
-                    label.to_string(),
-                    x.to_string(),
-                    y.to_string(),
-                    z.to_string(),
-                ]
+                kmeans::common::get_closest_cluster_index_based_in_centroids(point, &fitted.centers)
             })
-    } else {
-        todo!()
+            .collect()
     };
 
-    // Write to output file
-    let mut csv_writer = csv::WriterBuilder::new();
-    let csv_write = csv_writer.has_headers(false).delimiter(b' ');
-
-    match matches.output_file {
-        Some(path) => {
-            let mut writer = csv_write.from_path(path)?;
-            for row in output_values {
+    // Unlike the in-memory modes, entries are written in input order
+    if io.output_format() == Format::Ndjson {
+        let mut output = BufWriter::new(output_writer(io.output_file.as_deref())?);
+        source.for_each_chunk(&mut |chunk| {
+            let lines = chunk.iter().zip(assign(&chunk)).map(|(point, index)| {
+                json_assignment(point, index, &fitted.centers[index], io, &preprocessing)
+            });
+            json::write_ndjson(&mut output, lines)?;
+            Ok(ControlFlow::Continue(()))
+        })?;
+    } else {
+        let mut writer = csv_writer(io.output_file.as_deref())?;
+        source.for_each_chunk(&mut |chunk| {
+            for (point, index) in chunk.iter().zip(assign(&chunk)) {
+                let row = if io.replace_entry {
+                    values_row(point.get_label().unwrap_or("--"), &centers[index], color)
+                } else {
+                    assignment_row(point, index, &fitted.centers[index], io, &preprocessing)
+                };
                 writer.write_record(row)?;
             }
-        }
-        None => {
-            let mut writer = csv_write.from_writer(std::io::stdout());
-            for row in output_values {
-                writer.write_record(row)?;
+            Ok(ControlFlow::Continue(()))
+        })?;
+        writer.flush()?;
+    }
+    rows::report_skipped(source.skipped.get().unwrap_or(0));
+    Ok(())
+}
+
+/// Online kmeans over STDIN, until the end of the stream
+fn run_online(args: &OnlineArgs) -> Result<(), Box<dyn Error>> {
+    let preprocessing = args.preprocessing.preprocessing();
+    let emit = online::Emit {
+        every: args.every,
+        interval: args.interval_ms.map(Duration::from_millis),
+    };
+    let mut kmeans = online::OnlineKmeans::new(args.k, args.learning_rate);
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let mut errors = RowErrors::new(args.on_error);
+    runtime.block_on(async {
+        let input = tokio::io::BufReader::new(tokio::io::stdin());
+        match &args.output_file {
+            Some(path) => {
+                let output = tokio::fs::File::create(path).await?;
+                online::run(
+                    input,
+                    output,
+                    &mut kmeans,
+                    &preprocessing,
+                    &emit,
+                    &mut errors,
+                )
+                .await
+            }
+            None => {
+                let output = tokio::io::stdout();
+                online::run(
+                    input,
+                    output,
+                    &mut kmeans,
+                    &preprocessing,
+                    &emit,
+                    &mut errors,
+                )
+                .await
             }
         }
+    })?;
+    rows::report_skipped(errors.skipped());
+    Ok(())
+}
+
+fn run(args: &input::Args) -> Result<(), Box<dyn Error>> {
+    match &args.command {
+        None => fit(&args.cluster, &args.io, None),
+        Some(Command::Fit(fit_args)) => fit(
+            &fit_args.cluster,
+            &fit_args.io,
+            Some(fit_args.model_out.as_path()),
+        ),
+        Some(Command::Predict(predict_args)) => predict(predict_args),
+        Some(Command::Bench(bench_args)) => run_bench(bench_args),
+        Some(Command::Stream(stream_args)) => run_stream(stream_args),
+        Some(Command::Online(online_args)) => run_online(online_args),
     }
+}
 
-    Ok(())
+/// Silent unless asked for with `-v`
+fn init_logging(verbose: u8) {
+    let level = match verbose {
+        0 => log::LevelFilter::Off,
+        1 => log::LevelFilter::Info,
+        2 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    };
+    env_logger::Builder::new()
+        .filter_module(env!("CARGO_CRATE_NAME"), level)
+        .format(|buf, record| {
+            writeln!(
+                buf,
+                "{}: {}",
+                record.level().as_str().to_lowercase(),
+                record.args()
+            )
+        })
+        .init();
+}
+
+fn main() -> ExitCode {
+    let args = input::Args::parse();
+    init_logging(args.verbose);
+
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {error}");
+            ExitCode::FAILURE
+        }
+    }
 }
 
 #[cfg(test)]
@@ -138,17 +983,17 @@ mod tests {
         let data = vec![Point::from([1, 2]), Point::from([5, 8])];
 
         let k = 2;
-        let initial_centers = data.iter().take(k as usize).cloned().collect();
+        let initial_centers = data.iter().take(k).cloned().collect();
 
-        let clusters_output = KmeansSerialBuilder::default().execute(&data, k, initial_centers);
+        let clusters_output = KmeansSerialBuilder.execute(&data, k, initial_centers);
 
         let clusters_output_set: HashSet<Cluster> = HashSet::from_iter(clusters_output.clone());
 
-        assert_eq!(clusters_output.len(), k as usize);
+        assert_eq!(clusters_output.len(), k);
 
         // Expected Cluster 1
         let center = Point::from([1, 2]);
-        let points = vec![Point::from([1, 2])];
+        let points = [Point::from([1, 2])];
         let cluster1 = Cluster {
             center,
             points: points.iter().collect(),
@@ -156,7 +1001,7 @@ mod tests {
 
         // Expected Cluster 2
         let center = Point::from([5, 8]);
-        let points = vec![Point::from([5, 8])];
+        let points = [Point::from([5, 8])];
         let cluster2 = Cluster {
             center,
             points: points.iter().collect(),
@@ -173,15 +1018,15 @@ mod tests {
             .map(Point::from)
             .to_vec();
         let k = 2;
-        let initial_centers = data.iter().take(k as usize).cloned().collect();
+        let initial_centers = data.iter().take(k).cloned().collect();
 
-        let clusters_output = KmeansSerialBuilder::default().execute(&data, k, initial_centers);
+        let clusters_output = KmeansSerialBuilder.execute(&data, k, initial_centers);
 
         let clusters_output_set: HashSet<Cluster> = HashSet::from_iter(clusters_output.clone());
 
         // Expected Cluster 1
         let center = Point::from([1.5, 2.5]);
-        let points = vec![Point::from([1, 2]), Point::from([2, 3])];
+        let points = [Point::from([1, 2]), Point::from([2, 3])];
         let cluster1 = Cluster {
             center,
             points: points.iter().collect(),
@@ -189,7 +1034,7 @@ mod tests {
 
         // Expected Cluster 2
         let center = Point::from([9, 11]);
-        let points = vec![
+        let points = [
             Point::from([8, 10]),
             Point::from([9, 11]),
             Point::from([10, 12]),
@@ -210,15 +1055,15 @@ mod tests {
             .map(Point::from)
             .to_vec();
         let k = 3;
-        let initial_centers = data.iter().take(k as usize).cloned().collect();
+        let initial_centers = data.iter().take(k).cloned().collect();
 
-        let clusters_output = KmeansSerialBuilder::default().execute(&data, k, initial_centers);
+        let clusters_output = KmeansSerialBuilder.execute(&data, k, initial_centers);
 
         let clusters_output_set: HashSet<Cluster> = HashSet::from_iter(clusters_output.clone());
 
         // Expected Cluster 1
         let center = Point::from([1.5, 1.5]);
-        let points = vec![Point::from([1, 1]), Point::from([2, 2])];
+        let points = [Point::from([1, 1]), Point::from([2, 2])];
         let cluster1 = Cluster {
             center,
             points: points.iter().collect(),
@@ -226,7 +1071,7 @@ mod tests {
 
         // Expected Cluster 2
         let center = Point::from([8.5, 8.5]);
-        let points = vec![Point::from([8, 8]), Point::from([9, 9])];
+        let points = [Point::from([8, 8]), Point::from([9, 9])];
         let cluster2 = Cluster {
             center,
             points: points.iter().collect(),
@@ -234,7 +1079,7 @@ mod tests {
 
         // Expected Cluster 2
         let center = Point::from([20.5, 20.5]);
-        let points = vec![Point::from([20, 20]), Point::from([21, 21])];
+        let points = [Point::from([20, 20]), Point::from([21, 21])];
         let cluster3 = Cluster {
             center,
             points: points.iter().collect(),
@@ -244,4 +1089,43 @@ mod tests {
 
         assert_eq!(expected_set, clusters_output_set);
     }
+
+    #[test]
+    fn test_modes_return_the_same_cluster_order() {
+        let data = (0..200)
+            .map(|n| Point::from([(n * 37) % 101, (n * 53) % 97]))
+            .collect::<Vec<_>>();
+        let k = 4;
+        let init = InitArgs {
+            k,
+            random_initial: false,
+            seed: None,
+        };
+        let initial_centers = initial_centers(&data, &init);
+
+        let serial = runner(Mode::S, 1).execute(&data, k, initial_centers.clone());
+
+        for &mode in Mode::value_variants() {
+            let clusters = runner(mode, 4).execute(&data, k, initial_centers.clone());
+            assert_eq!(clusters, serial, "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn test_more_than_255_clusters() {
+        let data = (0..300).map(|n| Point::from([n])).collect::<Vec<_>>();
+        let k = 300;
+        let init = InitArgs {
+            k,
+            random_initial: false,
+            seed: None,
+        };
+        let initial_centers = initial_centers(&data, &init);
+
+        for &mode in Mode::value_variants() {
+            let clusters = runner(mode, 4).execute(&data, k, initial_centers.clone());
+            assert_eq!(clusters.len(), k, "{mode:?}");
+            assert!(clusters.iter().all(|cluster| cluster.points.len() == 1));
+        }
+    }
 }
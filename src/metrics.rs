@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+
+/// External validation scores of a clustering compared to ground-truth classes
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternalMetrics {
+    pub adjusted_rand_index: f64,
+    pub normalized_mutual_information: f64,
+    pub homogeneity: f64,
+    pub completeness: f64,
+    pub v_measure: f64,
+    pub purity: f64,
+}
+
+impl ExternalMetrics {
+    /// `truth` and `predicted` are the class and the cluster of each point, in the same order
+    pub fn compute<T, P>(truth: &[T], predicted: &[P]) -> Self
+    where
+        T: Eq + Hash,
+        P: Eq + Hash,
+    {
+        assert_eq!(truth.len(), predicted.len());
+        let n = truth.len() as f64;
+
+        let mut contingency: HashMap<(&T, &P), usize> = HashMap::new();
+        let mut classes: HashMap<&T, usize> = HashMap::new();
+        let mut clusters: HashMap<&P, usize> = HashMap::new();
+        for (t, p) in truth.iter().zip(predicted) {
+            *contingency.entry((t, p)).or_default() += 1;
+            *classes.entry(t).or_default() += 1;
+            *clusters.entry(p).or_default() += 1;
+        }
+
+        // Adjusted Rand index
+        let sum_comb_cells: f64 = contingency.values().map(|&c| comb2(c)).sum();
+        let sum_comb_classes: f64 = classes.values().map(|&c| comb2(c)).sum();
+        let sum_comb_clusters: f64 = clusters.values().map(|&c| comb2(c)).sum();
+        let expected = sum_comb_classes * sum_comb_clusters / comb2(truth.len()).max(1.0);
+        let max_index = (sum_comb_classes + sum_comb_clusters) / 2.0;
+        let adjusted_rand_index = if max_index == expected {
+            1.0
+        } else {
+            (sum_comb_cells - expected) / (max_index - expected)
+        };
+
+        // Information based scores
+        let entropy_classes = entropy(classes.values().copied(), n);
+        let entropy_clusters = entropy(clusters.values().copied(), n);
+        let mutual_information: f64 = contingency
+            .iter()
+            .map(|((t, p), &c)| {
+                let c = c as f64;
+                let (a, b) = (classes[t] as f64, clusters[p] as f64);
+                (c / n) * (n * c / (a * b)).ln()
+            })
+            .sum();
+
+        let homogeneity = if entropy_classes == 0.0 {
+            1.0
+        } else {
+            mutual_information / entropy_classes
+        };
+        let completeness = if entropy_clusters == 0.0 {
+            1.0
+        } else {
+            mutual_information / entropy_clusters
+        };
+        let v_measure = if homogeneity + completeness == 0.0 {
+            0.0
+        } else {
+            2.0 * homogeneity * completeness / (homogeneity + completeness)
+        };
+        let normalized_mutual_information = if entropy_classes + entropy_clusters == 0.0 {
+            1.0
+        } else {
+            2.0 * mutual_information / (entropy_classes + entropy_clusters)
+        };
+
+        // Purity
+        let mut majority: HashMap<&P, usize> = HashMap::new();
+        for ((_, p), &c) in &contingency {
+            let best = majority.entry(p).or_default();
+            *best = (*best).max(c);
+        }
+        let purity = majority.values().sum::<usize>() as f64 / n.max(1.0);
+
+        Self {
+            adjusted_rand_index,
+            normalized_mutual_information,
+            homogeneity,
+            completeness,
+            v_measure,
+            purity,
+        }
+    }
+}
+
+impl fmt::Display for ExternalMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "adjusted_rand_index: {:.6}", self.adjusted_rand_index)?;
+        writeln!(
+            f,
+            "normalized_mutual_information: {:.6}",
+            self.normalized_mutual_information
+        )?;
+        writeln!(f, "homogeneity: {:.6}", self.homogeneity)?;
+        writeln!(f, "completeness: {:.6}", self.completeness)?;
+        writeln!(f, "v_measure: {:.6}", self.v_measure)?;
+        write!(f, "purity: {:.6}", self.purity)
+    }
+}
+
+fn comb2(n: usize) -> f64 {
+    let n = n as f64;
+    n * (n - 1.0) / 2.0
+}
+
+fn entropy(counts: impl Iterator<Item = usize>, n: f64) -> f64 {
+    counts
+        .map(|c| c as f64 / n)
+        .filter(|&p| p > 0.0)
+        .map(|p| -p * p.ln())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-6, "{a} != {b}");
+    }
+
+    #[test]
+    fn test_perfect_match_with_permuted_ids() {
+        let truth = ["a", "a", "b", "b", "c", "c"];
+        let predicted = [2, 2, 0, 0, 1, 1];
+
+        let metrics = ExternalMetrics::compute(&truth, &predicted);
+
+        assert_close(metrics.adjusted_rand_index, 1.0);
+        assert_close(metrics.normalized_mutual_information, 1.0);
+        assert_close(metrics.homogeneity, 1.0);
+        assert_close(metrics.completeness, 1.0);
+        assert_close(metrics.v_measure, 1.0);
+        assert_close(metrics.purity, 1.0);
+    }
+
+    #[test]
+    fn test_single_cluster_is_complete_but_not_homogeneous() {
+        let truth = ["a", "a", "b", "b"];
+        let predicted = [0, 0, 0, 0];
+
+        let metrics = ExternalMetrics::compute(&truth, &predicted);
+
+        assert_close(metrics.adjusted_rand_index, 0.0);
+        assert_close(metrics.homogeneity, 0.0);
+        assert_close(metrics.completeness, 1.0);
+        assert_close(metrics.v_measure, 0.0);
+        assert_close(metrics.purity, 0.5);
+    }
+
+    #[test]
+    fn test_known_values() {
+        // Reference values from scikit-learn
+        let truth = [0, 0, 0, 1, 1, 1];
+        let predicted = [0, 0, 1, 1, 2, 2];
+
+        let metrics = ExternalMetrics::compute(&truth, &predicted);
+
+        assert_close(metrics.adjusted_rand_index, 0.242424);
+        assert_close(metrics.homogeneity, 0.666667);
+        assert_close(metrics.completeness, 0.420620);
+        assert_close(metrics.v_measure, 0.515804);
+        assert_close(metrics.purity, 5.0 / 6.0);
+    }
+}
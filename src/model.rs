@@ -0,0 +1,161 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::color::ColorSpace;
+use crate::entities::{Cluster, Point};
+use crate::spatial;
+
+/// Distance used to assign entries to centers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Metric {
+    /// Squared euclidean distance, as minimized by kmeans
+    #[default]
+    Euclidean,
+}
+
+/// How an input entry is turned into the features that are clustered
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Preprocessing {
+    pub color_space: ColorSpace,
+    pub spatial_weight: Option<f64>,
+}
+
+impl Preprocessing {
+    /// Values of the entry, the first three being an RGB color converted to `color_space`,
+    /// followed by its weighted `X:Y` position when `spatial_weight` is set
+    pub fn features(&self, label: &str, values: &[f64]) -> Result<Vec<f64>, String> {
+        let mut values = values.to_vec();
+        if self.color_space != ColorSpace::Rgb {
+            let [r, g, b, ..] = values[..] else {
+                return Err(format!(
+                    "the {:?} color space needs 3 values, found {}",
+                    self.color_space,
+                    values.len()
+                ));
+            };
+            values[..3].copy_from_slice(&self.color_space.convert_rgb([r, g, b]));
+        }
+        if let Some(weight) = self.spatial_weight {
+            let (x, y) = spatial::parse_coordinates(label)
+                .ok_or_else(|| format!("label `{label}` is not in the X:Y format"))?;
+            values.extend([x, y].map(|n| f64::from(n) * weight));
+        }
+        Ok(values)
+    }
+
+    /// Entry values of `features`: the position is dropped and the color converted back to RGB
+    pub fn restore(&self, features: &[f64]) -> Vec<f64> {
+        let spatial = if self.spatial_weight.is_some() { 2 } else { 0 };
+        let mut values = features[..features.len().saturating_sub(spatial)].to_vec();
+        if let [r, g, b, ..] = values[..] {
+            values[..3].copy_from_slice(&self.color_space.to_rgb([r, g, b]));
+        }
+        values
+    }
+}
+
+/// Fitted centers, saved by `fit` and reused by `predict`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Model {
+    pub version: u32,
+    pub metric: Metric,
+    pub preprocessing: Preprocessing,
+    pub centers: Vec<Vec<f64>>,
+}
+
+impl Model {
+    pub const VERSION: u32 = 1;
+
+    pub fn new(preprocessing: Preprocessing, clusters: &[Cluster]) -> Self {
+        Self {
+            version: Self::VERSION,
+            metric: Metric::Euclidean,
+            preprocessing,
+            centers: clusters
+                .iter()
+                .map(|cluster| cluster.center.get_data().to_vec())
+                .collect(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let model: Self = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        if model.version != Self::VERSION {
+            return Err(format!(
+                "model version {} is not supported, expected {}",
+                model.version,
+                Self::VERSION
+            )
+            .into());
+        }
+        if model.centers.is_empty() {
+            return Err("model has no centers".into());
+        }
+        Ok(model)
+    }
+
+    /// Empty clusters around the saved centers
+    pub fn clusters(&self) -> Vec<Cluster<'static>> {
+        self.centers
+            .iter()
+            .map(|center| Cluster::from_center(Point::from_vec(center.clone())))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_round_trip() {
+        let preprocessing = Preprocessing {
+            color_space: ColorSpace::Lab,
+            spatial_weight: Some(0.5),
+        };
+        let clusters = [
+            Cluster::from_center(Point::from([1.5, 2.0, 3.0, 4.0, 5.0])),
+            Cluster::from_center(Point::from([10, 20, 30, 40, 50])),
+        ];
+        let model = Model::new(preprocessing, &clusters);
+
+        let json = serde_json::to_string(&model).unwrap();
+        assert!(json.contains(r#""color_space":"lab""#));
+        assert!(json.contains(r#""metric":"euclidean""#));
+
+        let loaded: Model = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, model);
+        assert_eq!(loaded.clusters().to_vec(), clusters.to_vec());
+    }
+
+    #[test]
+    fn test_preprocessing_features() {
+        let preprocessing = Preprocessing {
+            color_space: ColorSpace::Rgb,
+            spatial_weight: Some(2.0),
+        };
+        assert_eq!(
+            preprocessing.features("3:4", &[10.0, 20.0, 30.0]),
+            Ok(vec![10.0, 20.0, 30.0, 6.0, 8.0])
+        );
+        assert!(preprocessing.features("abc", &[10.0, 20.0, 30.0]).is_err());
+
+        let preprocessing = Preprocessing {
+            color_space: ColorSpace::Lab,
+            spatial_weight: None,
+        };
+        assert!(preprocessing.features("a", &[1.0, 2.0]).is_err());
+    }
+}
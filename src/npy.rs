@@ -0,0 +1,309 @@
+use std::error::Error;
+use std::io::{Read, Write};
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+/// Element type of a `.npy` array
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Dtype {
+    F32,
+    F64,
+    U8,
+    I64,
+}
+
+impl Dtype {
+    fn parse(descr: &str) -> Result<Self, Box<dyn Error>> {
+        match descr {
+            "<f4" => Ok(Dtype::F32),
+            "<f8" => Ok(Dtype::F64),
+            "|u1" | "<u1" | "u1" => Ok(Dtype::U8),
+            "<i8" => Ok(Dtype::I64),
+            _ => Err(format!("unsupported .npy dtype `{descr}`, expected <f4, <f8 or |u1").into()),
+        }
+    }
+
+    fn descr(self) -> &'static str {
+        match self {
+            Dtype::F32 => "<f4",
+            Dtype::F64 => "<f8",
+            Dtype::U8 => "|u1",
+            Dtype::I64 => "<i8",
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Dtype::F32 => 4,
+            Dtype::F64 | Dtype::I64 => 8,
+            Dtype::U8 => 1,
+        }
+    }
+
+    fn value(self, bytes: &[u8]) -> f64 {
+        match self {
+            Dtype::F32 => f32::from_le_bytes(bytes.try_into().unwrap()).into(),
+            Dtype::F64 => f64::from_le_bytes(bytes.try_into().unwrap()),
+            Dtype::U8 => bytes[0].into(),
+            Dtype::I64 => i64::from_le_bytes(bytes.try_into().unwrap()) as f64,
+        }
+    }
+}
+
+/// Text of the `key` entry of a `.npy` header dictionary, up to the next `,` or the
+/// closing delimiter of a tuple
+fn header_value<'a>(header: &'a str, key: &str) -> Result<&'a str, Box<dyn Error>> {
+    let start = header
+        .find(&format!("'{key}'"))
+        .map(|index| index + key.len() + 2)
+        .ok_or_else(|| format!("no `{key}` in the .npy header"))?;
+    let value = header[start..]
+        .trim_start()
+        .trim_start_matches(':')
+        .trim_start();
+    let end = match value.starts_with('(') {
+        true => value.find(')').map(|index| index + 1),
+        false => value.find([',', '}']),
+    };
+    Ok(value[..end.unwrap_or(value.len())].trim())
+}
+
+/// Rows of a 1 or 2 dimensional `.npy` array of float32, float64 or uint8 values.
+/// A 1 dimensional array has one value per row
+pub fn read_npy(mut reader: impl Read) -> Result<Vec<Vec<f64>>, Box<dyn Error>> {
+    let mut preamble = [0; 8];
+    reader.read_exact(&mut preamble)?;
+    if &preamble[..6] != MAGIC {
+        return Err("not a .npy file".into());
+    }
+    let header_len = match preamble[6] {
+        1 => {
+            let mut len = [0; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_le_bytes(len) as usize
+        }
+        2 | 3 => {
+            let mut len = [0; 4];
+            reader.read_exact(&mut len)?;
+            u32::from_le_bytes(len) as usize
+        }
+        version => return Err(format!(".npy version {version} is not supported").into()),
+    };
+    let mut header = vec![0; header_len];
+    reader.read_exact(&mut header)?;
+    let header = String::from_utf8(header)?;
+
+    let dtype = Dtype::parse(header_value(&header, "descr")?.trim_matches(['\'', '"']))?;
+    let fortran_order = header_value(&header, "fortran_order")? == "True";
+    let shape = header_value(&header, "shape")?
+        .trim_matches(['(', ')'])
+        .split(',')
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+        .map(|n| n.parse::<usize>())
+        .collect::<Result<Vec<_>, _>>()?;
+    let (rows, columns) = match shape[..] {
+        [rows] => (rows, 1),
+        [rows, columns] => (rows, columns),
+        _ => {
+            return Err(format!("expected a 1 or 2 dimensional .npy array, found {shape:?}").into())
+        }
+    };
+
+    // The shape is not trusted to size a buffer: the data is read up to its expected length
+    let len = rows
+        .checked_mul(columns)
+        .and_then(|len| len.checked_mul(dtype.size()))
+        .ok_or_else(|| format!(".npy shape {shape:?} is too large"))?;
+    let mut bytes = Vec::new();
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() < len {
+        return Err(format!(".npy data is truncated, expected {len} bytes").into());
+    }
+    let values: Vec<f64> = bytes
+        .chunks_exact(dtype.size())
+        .map(|bytes| dtype.value(bytes))
+        .collect();
+    Ok((0..rows)
+        .map(|row| {
+            (0..columns)
+                .map(|column| match fortran_order {
+                    true => values[column * rows + row],
+                    false => values[row * columns + column],
+                })
+                .collect()
+        })
+        .collect())
+}
+
+fn write_npy(
+    mut writer: impl Write,
+    dtype: Dtype,
+    shape: &[usize],
+    data: &[u8],
+) -> Result<(), Box<dyn Error>> {
+    let shape = match shape {
+        [len] => format!("({len},)"),
+        _ => format!(
+            "({})",
+            shape
+                .iter()
+                .map(usize::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': {shape}, }}",
+        dtype.descr()
+    );
+    // The data starts on a 64 byte boundary, the header ending with a newline
+    let unpadded = MAGIC.len() + 4 + header.len() + 1;
+    header.extend(std::iter::repeat_n(
+        ' ',
+        unpadded.next_multiple_of(64) - unpadded,
+    ));
+    header.push('\n');
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[1, 0])?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    writer.write_all(data)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes `rows` as a 2 dimensional float64 array
+pub fn write_f64(writer: impl Write, rows: &[Vec<f64>]) -> Result<(), Box<dyn Error>> {
+    let columns = rows.first().map_or(0, Vec::len);
+    let data: Vec<u8> = rows
+        .iter()
+        .flatten()
+        .flat_map(|v| v.to_le_bytes())
+        .collect();
+    write_npy(writer, Dtype::F64, &[rows.len(), columns], &data)
+}
+
+/// Writes `values` as a 1 dimensional int64 array
+pub fn write_i64(writer: impl Write, values: &[i64]) -> Result<(), Box<dyn Error>> {
+    let data: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+    write_npy(writer, Dtype::I64, &[values.len()], &data)
+}
+
+/// Vectors of an ANN-benchmark `.fvecs` file of float32 values
+pub fn read_fvecs(reader: impl Read) -> Result<Vec<Vec<f64>>, Box<dyn Error>> {
+    read_vecs(reader, Dtype::F32)
+}
+
+/// Vectors of an ANN-benchmark `.bvecs` file of uint8 values
+pub fn read_bvecs(reader: impl Read) -> Result<Vec<Vec<f64>>, Box<dyn Error>> {
+    read_vecs(reader, Dtype::U8)
+}
+
+/// Each vector is its little-endian int32 dimension followed by its values
+fn read_vecs(mut reader: impl Read, dtype: Dtype) -> Result<Vec<Vec<f64>>, Box<dyn Error>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    let mut rows = Vec::new();
+    let mut rest = &bytes[..];
+    while !rest.is_empty() {
+        let (dimension, values) = rest
+            .split_first_chunk::<4>()
+            .ok_or("truncated vector dimension")?;
+        let dimension = usize::try_from(i32::from_le_bytes(*dimension))
+            .map_err(|_| "negative vector dimension")?;
+        let len = dimension * dtype.size();
+        if values.len() < len {
+            return Err(format!("vector {} is truncated", rows.len()).into());
+        }
+        rows.push(
+            values[..len]
+                .chunks_exact(dtype.size())
+                .map(|bytes| dtype.value(bytes))
+                .collect(),
+        );
+        rest = &values[len..];
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_npy_round_trip() {
+        let rows = vec![vec![1.5, 2.0, -3.0], vec![4.0, 5.0, 6.25]];
+        let mut bytes = Vec::new();
+        write_f64(&mut bytes, &rows).unwrap();
+        assert_eq!(bytes[8..10], [118, 0]);
+        assert_eq!(read_npy(bytes.as_slice()).unwrap(), rows);
+
+        let mut bytes = Vec::new();
+        write_i64(&mut bytes, &[3, 1]).unwrap();
+        assert_eq!(
+            read_npy(bytes.as_slice()).unwrap(),
+            vec![vec![3.0], vec![1.0]]
+        );
+    }
+
+    #[test]
+    fn test_read_npy_dtypes() {
+        // As written by numpy 1.x
+        let npy = |descr: &str, fortran: &str, shape: &str, data: &[u8]| {
+            let header =
+                format!("{{'descr': '{descr}', 'fortran_order': {fortran}, 'shape': {shape}, }}\n");
+            let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+            bytes.extend((header.len() as u16).to_le_bytes());
+            bytes.extend(header.as_bytes());
+            bytes.extend(data);
+            bytes
+        };
+
+        let data: Vec<u8> = [1.0f32, 2.0, 3.0, 4.0]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let c = npy("<f4", "False", "(2, 2)", &data);
+        assert_eq!(
+            read_npy(c.as_slice()).unwrap(),
+            vec![vec![1.0, 2.0], vec![3.0, 4.0]]
+        );
+        let fortran = npy("<f4", "True", "(2, 2)", &data);
+        assert_eq!(
+            read_npy(fortran.as_slice()).unwrap(),
+            vec![vec![1.0, 3.0], vec![2.0, 4.0]]
+        );
+        let bytes = npy("|u1", "False", "(1, 3)", &[7, 8, 255]);
+        assert_eq!(
+            read_npy(bytes.as_slice()).unwrap(),
+            vec![vec![7.0, 8.0, 255.0]]
+        );
+        assert!(read_npy(npy(">f8", "False", "(1,)", &[0; 8]).as_slice()).is_err());
+        assert!(read_npy(npy("<f8", "False", "(2,)", &[0; 8]).as_slice()).is_err());
+        let huge = format!("({}, 2)", usize::MAX / 2);
+        assert!(read_npy(npy("<f8", "False", &huge, &[]).as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_read_vecs() {
+        let mut fvecs = Vec::new();
+        for vector in [[1.0f32, 2.0], [3.0, 4.5]] {
+            fvecs.extend(2i32.to_le_bytes());
+            fvecs.extend(vector.iter().flat_map(|v| v.to_le_bytes()));
+        }
+        assert_eq!(
+            read_fvecs(fvecs.as_slice()).unwrap(),
+            vec![vec![1.0, 2.0], vec![3.0, 4.5]]
+        );
+
+        let bvecs = [3, 0, 0, 0, 10, 20, 30];
+        assert_eq!(
+            read_bvecs(bvecs.as_slice()).unwrap(),
+            vec![vec![10.0, 20.0, 30.0]]
+        );
+        assert!(read_bvecs(&bvecs[..6]).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+use std::error::Error;
+use std::time::Duration;
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::color::ColorSpace;
+use crate::entities::Point;
+use crate::kmeans::common;
+use crate::model::Preprocessing;
+use crate::rows::{Layout, RowErrors};
+
+/// Sequential kmeans (MacQueen, 1967): every point moves its closest center towards it
+pub struct OnlineKmeans {
+    k: usize,
+    /// Fixed step towards each point. When missing, a center is the running mean of its points
+    learning_rate: Option<f64>,
+    centers: Vec<Point>,
+    counts: Vec<u64>,
+}
+
+impl OnlineKmeans {
+    pub fn new(k: usize, learning_rate: Option<f64>) -> Self {
+        Self {
+            k,
+            learning_rate,
+            centers: Vec::with_capacity(k),
+            counts: Vec::with_capacity(k),
+        }
+    }
+
+    /// Adds a point and returns the index of its cluster.
+    /// The first `k` distinct points become the initial centers
+    pub fn update(&mut self, point: &Point) -> usize {
+        if self.centers.len() < self.k
+            && !self
+                .centers
+                .iter()
+                .any(|center| center.get_values() == point.get_values())
+        {
+            self.centers
+                .push(Point::from_vec(point.get_data().to_vec()));
+            self.counts.push(1);
+            return self.centers.len() - 1;
+        }
+
+        let index = common::get_closest_cluster_index_based_in_centroids(point, &self.centers);
+        self.counts[index] += 1;
+        let rate = self
+            .learning_rate
+            .unwrap_or(1.0 / self.counts[index] as f64);
+        self.centers[index] = self.centers[index].map_data(|center| {
+            center
+                .iter()
+                .zip(point.get_data())
+                .map(|(c, x)| c + rate * (x - c))
+                .collect()
+        });
+        index
+    }
+
+    pub fn centers(&self) -> impl Iterator<Item = (&Point, u64)> {
+        self.centers.iter().zip(self.counts.iter().copied())
+    }
+}
+
+/// When the centers are written out
+pub struct Emit {
+    /// After every this many points
+    pub every: Option<usize>,
+    /// On a timer, when points arrived since the last emission
+    pub interval: Option<Duration>,
+}
+
+/// Reads `X:Y R G B` lines until the end of `input`, writing the centers to `output` as
+/// `SEEN CLUSTER R G B COUNT` lines whenever `emit` says so, and once more at the end
+pub async fn run(
+    input: impl AsyncBufRead + Unpin,
+    mut output: impl AsyncWrite + Unpin,
+    kmeans: &mut OnlineKmeans,
+    preprocessing: &Preprocessing,
+    emit: &Emit,
+    errors: &mut RowErrors,
+) -> Result<(), Box<dyn Error>> {
+    let mut lines = input.lines();
+    let mut timer = emit
+        .interval
+        .map(|period| tokio::time::interval_at(tokio::time::Instant::now() + period, period));
+    let mut seen = 0;
+    let mut emitted = 0;
+    let mut line_number = 0;
+    let columns = Layout::default().resolve(None)?;
+
+    loop {
+        let line = match &mut timer {
+            Some(timer) => tokio::select! {
+                line = lines.next_line() => line?,
+                _ = timer.tick() => {
+                    if seen > emitted {
+                        write_centers(&mut output, kmeans, seen, preprocessing.color_space).await?;
+                        emitted = seen;
+                    }
+                    continue;
+                }
+            },
+            None => lines.next_line().await?,
+        };
+        let Some(line) = line else {
+            break;
+        };
+        line_number += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let Some((label, _, values, _)) = errors.handle(columns.parse(&fields, line_number))?
+        else {
+            continue;
+        };
+        let point = Point::from_vec(preprocessing.features(&label, &values)?);
+        kmeans.update(&point);
+        seen += 1;
+
+        if emit.every.is_some_and(|every| seen % every.max(1) == 0) {
+            write_centers(&mut output, kmeans, seen, preprocessing.color_space).await?;
+            emitted = seen;
+        }
+    }
+
+    if seen > emitted || seen == 0 {
+        write_centers(&mut output, kmeans, seen, preprocessing.color_space).await?;
+    }
+    Ok(())
+}
+
+async fn write_centers(
+    output: &mut (impl AsyncWrite + Unpin),
+    kmeans: &OnlineKmeans,
+    seen: usize,
+    color_space: ColorSpace,
+) -> Result<(), Box<dyn Error>> {
+    let mut text = String::new();
+    for (index, (center, count)) in kmeans.centers().enumerate() {
+        let [r, g, b] = color_space.to_rgb8(center.get_data());
+        text.push_str(&format!("{seen} {index} {r} {g} {b} {count}\n"));
+    }
+    output.write_all(text.as_bytes()).await?;
+    output.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rows::OnError;
+
+    #[test]
+    fn test_count_based_updates_are_running_means() {
+        let mut kmeans = OnlineKmeans::new(2, None);
+        for values in [[0, 0], [10, 10], [0, 0], [2, 2], [12, 12], [4, 4]] {
+            kmeans.update(&Point::from(values));
+        }
+        let centers: Vec<_> = kmeans.centers().collect();
+        assert!(centers[0].0.euclidean_distance(&Point::from([1.5, 1.5])) < 1e-12);
+        assert_eq!(centers[0].1, 4);
+        assert_eq!(centers[1], (&Point::from([11, 11]), 2));
+    }
+
+    #[test]
+    fn test_learning_rate() {
+        let mut kmeans = OnlineKmeans::new(1, Some(0.5));
+        kmeans.update(&Point::from([0, 0]));
+        kmeans.update(&Point::from([8, 4]));
+        kmeans.update(&Point::from([8, 4]));
+        assert_eq!(kmeans.centers().next(), Some((&Point::from([6, 3]), 3)));
+    }
+
+    #[tokio::test]
+    async fn test_emits_every_n_points() {
+        let input = "0:0 0 0 0\n0:1 2 2 2\n0:2 200 200 200\n0:3 202 202 202\n0:4 4 4 4\n";
+        let mut output = Vec::new();
+        let mut kmeans = OnlineKmeans::new(2, None);
+        let emit = Emit {
+            every: Some(2),
+            interval: None,
+        };
+
+        run(
+            input.as_bytes(),
+            &mut output,
+            &mut kmeans,
+            &Preprocessing::default(),
+            &emit,
+            &mut RowErrors::new(OnError::Fail),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "2 0 0 0 0 1\n2 1 2 2 2 1\n\
+             4 0 0 0 0 1\n4 1 135 135 135 3\n\
+             5 0 2 2 2 2\n5 1 135 135 135 3\n"
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use serde::Serialize;
+
+/// One centroid of the palette, with the number of entries of its cluster
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Swatch {
+    pub rgb: [u8; 3],
+    pub count: usize,
+}
+
+impl Swatch {
+    pub fn hex(&self) -> String {
+        let [r, g, b] = self.rgb;
+        format!("#{r:02x}{g:02x}{b:02x}")
+    }
+
+    fn name(&self, index: usize) -> String {
+        format!("Cluster {index} ({})", self.count)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum PaletteFormat {
+    Csv,
+    Json,
+    /// GIMP palette
+    Gpl,
+    /// Adobe swatch exchange
+    Ase,
+}
+
+impl PaletteFormat {
+    /// Format given by the extension of `path`
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("csv") => Ok(PaletteFormat::Csv),
+            Some("json") => Ok(PaletteFormat::Json),
+            Some("gpl") => Ok(PaletteFormat::Gpl),
+            Some("ase") => Ok(PaletteFormat::Ase),
+            _ => Err(format!(
+                "unknown palette format of `{}`, expected .csv, .json, .gpl or .ase",
+                path.display()
+            )),
+        }
+    }
+}
+
+pub fn write_palette(
+    path: &Path,
+    format: PaletteFormat,
+    swatches: &[Swatch],
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    match format {
+        PaletteFormat::Csv => write_csv(&mut writer, swatches)?,
+        PaletteFormat::Json => write_json(&mut writer, swatches)?,
+        PaletteFormat::Gpl => write_gpl(&mut writer, swatches)?,
+        PaletteFormat::Ase => write_ase(&mut writer, swatches)?,
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_csv(writer: impl Write, swatches: &[Swatch]) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(["index", "r", "g", "b", "hex", "count"])?;
+    for (index, swatch) in swatches.iter().enumerate() {
+        let [r, g, b] = swatch.rgb;
+        writer.write_record([
+            index.to_string(),
+            r.to_string(),
+            g.to_string(),
+            b.to_string(),
+            swatch.hex(),
+            swatch.count.to_string(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_json(writer: impl Write, swatches: &[Swatch]) -> Result<(), Box<dyn Error>> {
+    #[derive(Serialize)]
+    struct Entry<'a> {
+        index: usize,
+        hex: String,
+        #[serde(flatten)]
+        swatch: &'a Swatch,
+    }
+
+    let entries: Vec<Entry> = swatches
+        .iter()
+        .enumerate()
+        .map(|(index, swatch)| Entry {
+            index,
+            hex: swatch.hex(),
+            swatch,
+        })
+        .collect();
+    serde_json::to_writer_pretty(writer, &entries)?;
+    Ok(())
+}
+
+fn write_gpl(mut writer: impl Write, swatches: &[Swatch]) -> Result<(), Box<dyn Error>> {
+    writeln!(writer, "GIMP Palette")?;
+    writeln!(writer, "Name: kmeans")?;
+    writeln!(writer, "Columns: 0")?;
+    writeln!(writer, "#")?;
+    for (index, swatch) in swatches.iter().enumerate() {
+        let [r, g, b] = swatch.rgb;
+        writeln!(writer, "{r:3} {g:3} {b:3}\t{}", swatch.name(index))?;
+    }
+    Ok(())
+}
+
+/// Adobe Swatch Exchange 1.0: big-endian, one RGB color block per swatch
+fn write_ase(mut writer: impl Write, swatches: &[Swatch]) -> Result<(), Box<dyn Error>> {
+    const COLOR_ENTRY: u16 = 0x0001;
+    const GLOBAL_COLOR: u16 = 0;
+
+    writer.write_all(b"ASEF")?;
+    writer.write_all(&1u16.to_be_bytes())?;
+    writer.write_all(&0u16.to_be_bytes())?;
+    writer.write_all(&(swatches.len() as u32).to_be_bytes())?;
+
+    for (index, swatch) in swatches.iter().enumerate() {
+        let name: Vec<u16> = swatch.name(index).encode_utf16().chain([0]).collect();
+
+        let mut block = Vec::new();
+        block.extend((name.len() as u16).to_be_bytes());
+        block.extend(name.iter().flat_map(|c| c.to_be_bytes()));
+        block.extend(b"RGB ");
+        for channel in swatch.rgb {
+            block.extend((channel as f32 / 255.0).to_be_bytes());
+        }
+        block.extend(GLOBAL_COLOR.to_be_bytes());
+
+        writer.write_all(&COLOR_ENTRY.to_be_bytes())?;
+        writer.write_all(&(block.len() as u32).to_be_bytes())?;
+        writer.write_all(&block)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn swatches() -> Vec<Swatch> {
+        vec![
+            Swatch {
+                rgb: [255, 128, 0],
+                count: 10,
+            },
+            Swatch {
+                rgb: [0, 0, 0],
+                count: 3,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_gpl() {
+        let mut bytes = Vec::new();
+        write_gpl(&mut bytes, &swatches()).unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+        assert!(text.starts_with("GIMP Palette\n"));
+        assert!(text.contains("255 128   0\tCluster 0 (10)\n"));
+        assert!(text.contains("  0   0   0\tCluster 1 (3)\n"));
+    }
+
+    #[test]
+    fn test_ase_layout() {
+        let mut bytes = Vec::new();
+        write_ase(&mut bytes, &swatches()).unwrap();
+
+        assert_eq!(&bytes[..4], b"ASEF");
+        assert_eq!(u32::from_be_bytes(bytes[8..12].try_into().unwrap()), 2);
+
+        // First block: type, length, then name length in UTF-16 units with the trailing null
+        assert_eq!(u16::from_be_bytes([bytes[12], bytes[13]]), 0x0001);
+        let block_len = u32::from_be_bytes(bytes[14..18].try_into().unwrap()) as usize;
+        let name_len = "Cluster 0 (10)".len() + 1;
+        assert_eq!(
+            u16::from_be_bytes([bytes[18], bytes[19]]) as usize,
+            name_len
+        );
+        assert_eq!(block_len, 2 + name_len * 2 + 4 + 3 * 4 + 2);
+
+        let model = 20 + name_len * 2;
+        assert_eq!(&bytes[model..model + 4], b"RGB ");
+        let red = f32::from_be_bytes(bytes[model + 4..model + 8].try_into().unwrap());
+        assert_eq!(red, 1.0);
+    }
+
+    #[test]
+    fn test_json() {
+        let mut bytes = Vec::new();
+        write_json(&mut bytes, &swatches()).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(value[0]["hex"], "#ff8000");
+        assert_eq!(value[1]["count"], 3);
+        assert_eq!(value[1]["rgb"], serde_json::json!([0, 0, 0]));
+    }
+}
@@ -0,0 +1,575 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use clap::ValueEnum;
+
+use crate::image::MAX_PIXELS;
+
+/// Label, optional ground-truth class, feature values and weight of one input line
+pub type InputRow = (String, Option<String>, Vec<f64>, f64);
+
+/// What happens to an input row that can't be read
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug, Hash, Default)]
+pub enum OnError {
+    /// Drop the row
+    Skip,
+
+    /// Stop at the first invalid row
+    #[default]
+    Fail,
+
+    /// Drop the row and print why to stderr
+    Warn,
+}
+
+#[derive(Debug)]
+pub enum InputError {
+    Io(io::Error),
+
+    /// A row that doesn't have the expected fields
+    Parse {
+        line: u64,
+        content: String,
+        message: String,
+    },
+
+    /// A color value outside of 0-255
+    Range {
+        line: u64,
+        content: String,
+        value: f64,
+    },
+
+    /// A column name that is not in the header
+    UnknownColumn(String),
+
+    /// A label that is not an `X:Y` pixel position
+    NotAPixel(String),
+
+    /// A pixel position that more than one entry has
+    DuplicatePixel(String),
+
+    /// Pixel positions spread over a grid of more than [`MAX_PIXELS`] cells
+    SparsePixels {
+        width: usize,
+        height: usize,
+    },
+
+    /// No row could be read
+    Empty,
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputError::Io(error) => write!(f, "I/O error: {error}"),
+            InputError::Parse {
+                line,
+                content,
+                message,
+            } => write!(f, "line {line}: {message}: `{content}`"),
+            InputError::Range {
+                line,
+                content,
+                value,
+            } => write!(
+                f,
+                "line {line}: value {value} is out of the 0-255 range: `{content}`"
+            ),
+            InputError::UnknownColumn(name) => write!(f, "no column named `{name}` in the header"),
+            InputError::NotAPixel(label) => write!(f, "label `{label}` is not in the X:Y format"),
+            InputError::DuplicatePixel(label) => {
+                write!(f, "pixel `{label}` appears more than once")
+            }
+            InputError::SparsePixels { width, height } => write!(
+                f,
+                "pixel positions span a {width}x{height} grid, more than {MAX_PIXELS} pixels"
+            ),
+            InputError::Empty => write!(f, "the input has no valid rows"),
+        }
+    }
+}
+
+impl InputError {
+    /// Line of the invalid row, when the error is about a row
+    pub fn line(&self) -> Option<u64> {
+        match self {
+            InputError::Parse { line, .. } | InputError::Range { line, .. } => Some(*line),
+            InputError::Io(_)
+            | InputError::UnknownColumn(_)
+            | InputError::NotAPixel(_)
+            | InputError::DuplicatePixel(_)
+            | InputError::SparsePixels { .. }
+            | InputError::Empty => None,
+        }
+    }
+}
+
+impl Error for InputError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            InputError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for InputError {
+    fn from(error: io::Error) -> Self {
+        InputError::Io(error)
+    }
+}
+
+impl From<csv::Error> for InputError {
+    fn from(error: csv::Error) -> Self {
+        InputError::from_csv(error, &csv::ByteRecord::new())
+    }
+}
+
+impl InputError {
+    /// `error`, with the fields of `record` as the content when it is a parse error
+    fn from_csv(error: csv::Error, record: &csv::ByteRecord) -> Self {
+        let line = error.position().map_or(0, |position| position.line());
+        let message = error.to_string();
+        match error.into_kind() {
+            csv::ErrorKind::Io(error) => InputError::Io(error),
+            _ => InputError::Parse {
+                line,
+                content: record_content(record),
+                message,
+            },
+        }
+    }
+}
+
+fn record_content(record: &csv::ByteRecord) -> String {
+    record
+        .iter()
+        .map(String::from_utf8_lossy)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Like [`csv::Reader::records`], but errors keep the content of the record that caused them
+pub fn records<R: io::Read>(
+    reader: &mut csv::Reader<R>,
+) -> impl Iterator<Item = Result<csv::StringRecord, InputError>> + '_ {
+    let mut record = csv::ByteRecord::new();
+    std::iter::from_fn(move || match reader.read_byte_record(&mut record) {
+        Ok(false) => None,
+        Ok(true) => Some(
+            csv::StringRecord::from_byte_record(record.clone()).map_err(|error| {
+                InputError::Parse {
+                    line: record.position().map_or(0, |position| position.line()),
+                    content: record_content(&record),
+                    message: error.utf8_error().to_string(),
+                }
+            }),
+        ),
+        Err(error) => Some(Err(InputError::from_csv(error, &record))),
+    })
+}
+
+/// Applies an [`OnError`] policy to invalid rows and counts the skipped ones
+pub struct RowErrors {
+    policy: OnError,
+    skipped: usize,
+    /// Rows up to this line were already warned about, in a previous pass over the same input
+    quiet_until: u64,
+}
+
+impl RowErrors {
+    pub fn new(policy: OnError) -> Self {
+        Self {
+            policy,
+            skipped: 0,
+            quiet_until: 0,
+        }
+    }
+
+    pub fn quiet_until(mut self, line: u64) -> Self {
+        self.quiet_until = line;
+        self
+    }
+
+    /// The row, `None` when it is skipped, or the error when the policy is to fail.
+    /// I/O errors are never skipped
+    pub fn handle<T>(&mut self, row: Result<T, InputError>) -> Result<Option<T>, InputError> {
+        match (row, self.policy) {
+            (Ok(row), _) => Ok(Some(row)),
+            (Err(error @ InputError::Io(_)), _) | (Err(error), OnError::Fail) => Err(error),
+            (Err(error), OnError::Warn) => {
+                if error.line().is_none_or(|line| line > self.quiet_until) {
+                    eprintln!("warning: skipping {error}");
+                }
+                self.skipped += 1;
+                Ok(None)
+            }
+            (Err(_), OnError::Skip) => {
+                self.skipped += 1;
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+}
+
+/// Prints the number of skipped rows to stderr, when there are any
+pub fn report_skipped(skipped: usize) {
+    if skipped > 0 {
+        eprintln!("skipped {skipped} invalid rows");
+    }
+}
+
+/// A column of the input, by 0-based index or by header name
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+impl FromStr for Column {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "" => Err("empty column".to_string()),
+            s => Ok(s
+                .parse()
+                .map_or_else(|_| Column::Name(s.to_string()), Column::Index)),
+        }
+    }
+}
+
+impl Column {
+    fn resolve(&self, headers: Option<&csv::StringRecord>) -> Result<usize, InputError> {
+        match self {
+            Column::Index(index) => Ok(*index),
+            Column::Name(name) => headers
+                .and_then(|headers| headers.iter().position(|header| header.trim() == name))
+                .ok_or_else(|| InputError::UnknownColumn(name.clone())),
+        }
+    }
+}
+
+/// How the fields of a delimited input map onto points
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct Layout {
+    pub delimiter: u8,
+    pub has_headers: bool,
+    pub label: Column,
+    /// Ground-truth class of each row
+    pub ground_truth: bool,
+    /// Column of the class, the one right after the label when missing
+    pub class: Option<Column>,
+    /// When missing, the three columns after the label and class are RGB values in 0-255
+    pub features: Option<Vec<Column>>,
+    /// Positive weight of each row, 1 when missing
+    pub weight: Option<Column>,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            delimiter: b' ',
+            has_headers: false,
+            label: Column::Index(0),
+            ground_truth: false,
+            class: None,
+            features: None,
+            weight: None,
+        }
+    }
+}
+
+impl Layout {
+    pub fn reader_builder(&self) -> csv::ReaderBuilder {
+        let mut builder = csv::ReaderBuilder::new();
+        builder
+            .delimiter(self.delimiter)
+            .has_headers(self.has_headers)
+            .flexible(true);
+        builder
+    }
+
+    /// Column indexes, names being looked up in `headers`
+    pub fn resolve(&self, headers: Option<&csv::StringRecord>) -> Result<Columns, InputError> {
+        let label = self.label.resolve(headers)?;
+        let class = match (self.ground_truth, &self.class) {
+            (false, _) => None,
+            (true, Some(column)) => Some(column.resolve(headers)?),
+            (true, None) => Some(label + 1),
+        };
+        let first_color = label + 1 + usize::from(class == Some(label + 1));
+        let features = match &self.features {
+            Some(columns) => columns
+                .iter()
+                .map(|column| column.resolve(headers))
+                .collect::<Result<_, _>>()?,
+            None => (first_color..first_color + 3).collect(),
+        };
+        let weight = self
+            .weight
+            .as_ref()
+            .map(|column| column.resolve(headers))
+            .transpose()?;
+        Ok(Columns {
+            label,
+            class,
+            features,
+            weight,
+            colors: self.features.is_none(),
+        })
+    }
+}
+
+/// [`Layout`] with the column names resolved
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct Columns {
+    label: usize,
+    class: Option<usize>,
+    features: Vec<usize>,
+    weight: Option<usize>,
+    colors: bool,
+}
+
+impl Columns {
+    /// Parses the fields of line `line`. Color rows must have exactly the label, class,
+    /// color and weight fields, other rows at least the selected columns
+    pub fn parse(&self, fields: &[&str], line: u64) -> Result<InputRow, InputError> {
+        let content = || fields.join(" ");
+        let needed = self
+            .features
+            .iter()
+            .chain([&self.label])
+            .chain(&self.class)
+            .chain(&self.weight)
+            .max()
+            .map_or(0, |max| max + 1);
+        let valid_len = match self.colors {
+            true => fields.len() == needed,
+            false => fields.len() >= needed,
+        };
+        if !valid_len {
+            return Err(InputError::Parse {
+                line,
+                content: content(),
+                message: format!("expected {needed} fields, found {}", fields.len()),
+            });
+        }
+
+        let values = self
+            .features
+            .iter()
+            .map(|&column| {
+                let field = fields[column].trim();
+                // Colors are integers, like the pixels they come from
+                let value = match self.colors {
+                    true => field.parse::<u32>().ok().map(f64::from),
+                    false => field.parse::<f64>().ok().filter(|value| value.is_finite()),
+                };
+                let value = value.ok_or_else(|| InputError::Parse {
+                    line,
+                    content: content(),
+                    message: match self.colors {
+                        true => format!("`{field}` is not an integer"),
+                        false => format!("`{field}` is not a number"),
+                    },
+                })?;
+                if self.colors && !(0.0..=255.0).contains(&value) {
+                    return Err(InputError::Range {
+                        line,
+                        content: content(),
+                        value,
+                    });
+                }
+                Ok(value)
+            })
+            .collect::<Result<_, _>>()?;
+
+        let weight = match self.weight {
+            Some(column) => {
+                let field = fields[column].trim();
+                field
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|weight| weight.is_finite() && *weight > 0.0)
+                    .ok_or_else(|| InputError::Parse {
+                        line,
+                        content: content(),
+                        message: format!("weight `{field}` is not a positive number"),
+                    })?
+            }
+            None => 1.0,
+        };
+
+        let class = self.class.map(|class| fields[class].to_string());
+        Ok((fields[self.label].to_string(), class, values, weight))
+    }
+
+    pub fn parse_record(&self, record: &csv::StringRecord) -> Result<InputRow, InputError> {
+        let line = record.position().map_or(0, |position| position.line());
+        self.parse(&record.iter().collect::<Vec<_>>(), line)
+    }
+}
+
+/// Every valid row of `reader`, `errors` deciding what happens to the invalid ones
+pub fn read_rows<R: io::Read>(
+    reader: &mut csv::Reader<R>,
+    layout: &Layout,
+    errors: &mut RowErrors,
+) -> Result<Vec<InputRow>, InputError> {
+    let headers = match layout.has_headers {
+        true => Some(reader.headers()?.clone()),
+        false => None,
+    };
+    let columns = layout.resolve(headers.as_ref())?;
+
+    let mut rows = Vec::new();
+    for record in records(reader) {
+        let row = record.and_then(|record| columns.parse_record(&record));
+        if let Some(row) = errors.handle(row)? {
+            rows.push(row);
+        }
+    }
+    if rows.is_empty() {
+        return Err(InputError::Empty);
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(
+        text: &str,
+        layout: &Layout,
+        policy: OnError,
+    ) -> (Result<Vec<InputRow>, InputError>, usize) {
+        let mut reader = layout.reader_builder().from_reader(text.as_bytes());
+        let mut errors = RowErrors::new(policy);
+        let rows = read_rows(&mut reader, layout, &mut errors);
+        (rows, errors.skipped())
+    }
+
+    #[test]
+    fn test_errors_have_line_and_content() {
+        let text = "0:0 1 2 3\n0:1 1 two 3\n0:2 1 2 300\n0:3 1 2\n0:4 10.5 10 10\n";
+        let layout = Layout::default();
+
+        match read(text, &layout, OnError::Fail).0 {
+            Err(InputError::Parse { line, content, .. }) => {
+                assert_eq!(line, 2);
+                assert_eq!(content, "0:1 1 two 3");
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+
+        let (rows, skipped) = read(text, &layout, OnError::Skip);
+        assert_eq!(
+            rows.unwrap(),
+            vec![("0:0".to_string(), None, vec![1.0, 2.0, 3.0], 1.0)]
+        );
+        assert_eq!(skipped, 4);
+
+        let columns = layout.resolve(None).unwrap();
+        let error = columns.parse(&["0:2", "1", "2", "300"], 3).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "line 3: value 300 is out of the 0-255 range: `0:2 1 2 300`"
+        );
+    }
+
+    #[test]
+    fn test_csv_errors_keep_the_record() {
+        let text = b"0:0 1 2 3\n0:1 1 \xff 3\n";
+        let layout = Layout::default();
+        let mut reader = layout.reader_builder().from_reader(&text[..]);
+        let mut errors = RowErrors::new(OnError::Fail);
+
+        match read_rows(&mut reader, &layout, &mut errors) {
+            Err(InputError::Parse { line, content, .. }) => {
+                assert_eq!(line, 2);
+                assert_eq!(content, "0:1 1 \u{fffd} 3");
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_empty_input() {
+        let layout = Layout::default();
+        assert!(matches!(
+            read("", &layout, OnError::Fail).0,
+            Err(InputError::Empty)
+        ));
+        assert!(matches!(
+            read("a b c d\n", &layout, OnError::Skip).0,
+            Err(InputError::Empty)
+        ));
+    }
+
+    #[test]
+    fn test_columns_by_name_and_index() {
+        let text = "sepal_length,sepal_width,petal_length,species\n\
+                    5.1,3.5,1.4,setosa\n\
+                    6.3,2.5,5.0,virginica\n";
+        let layout = Layout {
+            delimiter: b',',
+            has_headers: true,
+            label: "species".parse().unwrap(),
+            ground_truth: true,
+            class: Some("species".parse().unwrap()),
+            features: Some(vec!["0".parse().unwrap(), "petal_length".parse().unwrap()]),
+            weight: None,
+        };
+
+        let rows = read(text, &layout, OnError::Fail).0.unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                (
+                    "setosa".to_string(),
+                    Some("setosa".to_string()),
+                    vec![5.1, 1.4],
+                    1.0
+                ),
+                (
+                    "virginica".to_string(),
+                    Some("virginica".to_string()),
+                    vec![6.3, 5.0],
+                    1.0
+                ),
+            ]
+        );
+
+        let layout = Layout {
+            features: Some(vec![Column::Name("petal".to_string())]),
+            ..layout
+        };
+        assert!(matches!(
+            read(text, &layout, OnError::Fail).0,
+            Err(InputError::UnknownColumn(_))
+        ));
+    }
+
+    #[test]
+    fn test_weight_column() {
+        let layout = Layout {
+            weight: Some(Column::Index(4)),
+            ..Layout::default()
+        };
+        let (rows, skipped) = read("a 1 2 3 2.5\nb 1 2 3 0\nc 1 2 3\n", &layout, OnError::Skip);
+        assert_eq!(
+            rows.unwrap(),
+            vec![("a".to_string(), None, vec![1.0, 2.0, 3.0], 2.5)]
+        );
+        assert_eq!(skipped, 2);
+    }
+}
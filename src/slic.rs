@@ -0,0 +1,373 @@
+use std::collections::VecDeque;
+
+use clap::ValueEnum;
+
+use crate::entities::Point;
+use crate::image::MAX_PIXELS;
+use crate::rows::InputError;
+
+/// What replaces each pixel in SLIC mode
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug, Hash)]
+pub enum SlicOutput {
+    /// Mean color of the superpixel
+    MeanColor,
+
+    /// Superpixel index, encoded as a 24 bits RGB value
+    Id,
+}
+
+/// SLIC superpixels (Achanta et al., 2012) of pixels laid out on a grid
+pub struct Slic {
+    pub superpixels: usize,
+    pub compactness: f64,
+    pub iterations: usize,
+}
+
+struct Center {
+    color: Point,
+    x: f64,
+    y: f64,
+}
+
+/// Position of each pixel inside a dense `width * height` grid
+struct Grid {
+    width: usize,
+    height: usize,
+    cells: Vec<Option<usize>>,
+}
+
+impl Grid {
+    /// Fails when the grid would be larger than an image may be
+    fn new(coordinates: &[(u32, u32)]) -> Result<Self, InputError> {
+        let width = coordinates
+            .iter()
+            .map(|&(x, _)| x as usize + 1)
+            .max()
+            .unwrap_or(0);
+        let height = coordinates
+            .iter()
+            .map(|&(_, y)| y as usize + 1)
+            .max()
+            .unwrap_or(0);
+        let len = width
+            .checked_mul(height)
+            .filter(|&len| len <= MAX_PIXELS)
+            .ok_or(InputError::SparsePixels { width, height })?;
+        let mut cells = vec![None; len];
+        for (i, &(x, y)) in coordinates.iter().enumerate() {
+            cells[y as usize * width + x as usize] = Some(i);
+        }
+        Ok(Self {
+            width,
+            height,
+            cells,
+        })
+    }
+
+    fn get(&self, x: isize, y: isize) -> Option<usize> {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return None;
+        }
+        self.cells[y as usize * self.width + x as usize]
+    }
+}
+
+impl Slic {
+    /// `coordinates` holds the pixel position of each point.
+    /// Returns the superpixel index of each point, every superpixel being connected
+    pub fn segment(
+        &self,
+        points: &[Point],
+        coordinates: &[(u32, u32)],
+    ) -> Result<Vec<usize>, InputError> {
+        assert_eq!(points.len(), coordinates.len());
+        if points.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let grid = Grid::new(coordinates)?;
+        let step = (points.len() as f64 / self.superpixels.max(1) as f64)
+            .sqrt()
+            .max(1.0);
+
+        let mut centers = self.seed_centers(points, &grid, step);
+        let mut labels = vec![usize::MAX; points.len()];
+
+        let spatial_factor = (self.compactness / step).powi(2);
+        let radius = step.ceil() as isize;
+
+        for _ in 0..self.iterations {
+            let mut distances = vec![f64::INFINITY; points.len()];
+
+            for (k, center) in centers.iter().enumerate() {
+                let (cx, cy) = (center.x.round() as isize, center.y.round() as isize);
+                for y in cy - radius..=cy + radius {
+                    for x in cx - radius..=cx + radius {
+                        let Some(i) = grid.get(x, y) else {
+                            continue;
+                        };
+                        let distance =
+                            self.distance(center, &points[i], coordinates[i], spatial_factor);
+                        if distance < distances[i] {
+                            distances[i] = distance;
+                            labels[i] = k;
+                        }
+                    }
+                }
+            }
+
+            // Pixels out of reach of every window (sparse inputs) go to the closest center
+            for i in 0..points.len() {
+                if labels[i] == usize::MAX {
+                    labels[i] = (0..centers.len())
+                        .min_by(|&a, &b| {
+                            let da = self.distance(
+                                &centers[a],
+                                &points[i],
+                                coordinates[i],
+                                spatial_factor,
+                            );
+                            let db = self.distance(
+                                &centers[b],
+                                &points[i],
+                                coordinates[i],
+                                spatial_factor,
+                            );
+                            da.total_cmp(&db)
+                        })
+                        .unwrap_or(0);
+                }
+            }
+
+            update_centers(&mut centers, points, coordinates, &labels);
+        }
+
+        Ok(enforce_connectivity(
+            &grid,
+            &labels,
+            points.len() / centers.len().max(1) / 4,
+        ))
+    }
+
+    fn distance(
+        &self,
+        center: &Center,
+        point: &Point,
+        (x, y): (u32, u32),
+        spatial_factor: f64,
+    ) -> f64 {
+        let color = center.color.euclidean_distance(point);
+        let spatial = (center.x - x as f64).powi(2) + (center.y - y as f64).powi(2);
+        color + spatial * spatial_factor
+    }
+
+    /// Regular grid of centers, each moved to the lowest gradient position of its 3x3 neighborhood
+    fn seed_centers(&self, points: &[Point], grid: &Grid, step: f64) -> Vec<Center> {
+        let gradient = |x: isize, y: isize| -> Option<f64> {
+            let left = grid.get(x - 1, y)?;
+            let right = grid.get(x + 1, y)?;
+            let up = grid.get(x, y - 1)?;
+            let down = grid.get(x, y + 1)?;
+            Some(
+                points[left].euclidean_distance(&points[right])
+                    + points[up].euclidean_distance(&points[down]),
+            )
+        };
+
+        let mut centers = Vec::new();
+        let mut y = step / 2.0;
+        while y < grid.height as f64 {
+            let mut x = step / 2.0;
+            while x < grid.width as f64 {
+                let (gx, gy) = (x as isize, y as isize);
+                let best = (-1..=1)
+                    .flat_map(|dy| (-1..=1).map(move |dx| (gx + dx, gy + dy)))
+                    .filter(|&(x, y)| grid.get(x, y).is_some())
+                    .min_by(|&a, &b| {
+                        let ga = gradient(a.0, a.1).unwrap_or(f64::INFINITY);
+                        let gb = gradient(b.0, b.1).unwrap_or(f64::INFINITY);
+                        ga.total_cmp(&gb)
+                    });
+                if let Some((bx, by)) = best {
+                    let i = grid.get(bx, by).unwrap();
+                    centers.push(Center {
+                        color: points[i].clone(),
+                        x: bx as f64,
+                        y: by as f64,
+                    });
+                }
+                x += step;
+            }
+            y += step;
+        }
+
+        if centers.is_empty() {
+            centers.push(Center {
+                color: points[0].clone(),
+                x: grid.width as f64 / 2.0,
+                y: grid.height as f64 / 2.0,
+            });
+        }
+        centers
+    }
+}
+
+fn update_centers(
+    centers: &mut [Center],
+    points: &[Point],
+    coordinates: &[(u32, u32)],
+    labels: &[usize],
+) {
+    let dimension = points[0].dimension();
+    let mut sums = vec![(vec![0.0; dimension], 0.0, 0.0, 0usize); centers.len()];
+    for ((point, &(x, y)), &label) in points.iter().zip(coordinates).zip(labels) {
+        let (color, sum_x, sum_y, count) = &mut sums[label];
+        for (sum, value) in color.iter_mut().zip(point.get_data()) {
+            *sum += value;
+        }
+        *sum_x += x as f64;
+        *sum_y += y as f64;
+        *count += 1;
+    }
+
+    for (center, (color, sum_x, sum_y, count)) in centers.iter_mut().zip(sums) {
+        if count == 0 {
+            continue;
+        }
+        let count = count as f64;
+        center.color = Point::from_vec(color.into_iter().map(|sum| sum / count).collect());
+        center.x = sum_x / count;
+        center.y = sum_y / count;
+    }
+}
+
+/// Relabels the 4-connected components of `labels`. Components smaller than `min_size`
+/// are merged into the previously visited neighbor component
+fn enforce_connectivity(grid: &Grid, labels: &[usize], min_size: usize) -> Vec<usize> {
+    const NEIGHBORS: [(isize, isize); 4] = [(-1, 0), (0, -1), (1, 0), (0, 1)];
+
+    let mut new_labels = vec![usize::MAX; labels.len()];
+    let mut next_label = 0;
+
+    for y in 0..grid.height as isize {
+        for x in 0..grid.width as isize {
+            let Some(start) = grid.get(x, y) else {
+                continue;
+            };
+            if new_labels[start] != usize::MAX {
+                continue;
+            }
+
+            let adjacent = NEIGHBORS
+                .iter()
+                .filter_map(|&(dx, dy)| grid.get(x + dx, y + dy))
+                .map(|i| new_labels[i])
+                .find(|&label| label != usize::MAX);
+
+            let mut component = vec![start];
+            let mut queue = VecDeque::from([(x, y)]);
+            new_labels[start] = next_label;
+            while let Some((cx, cy)) = queue.pop_front() {
+                for (dx, dy) in NEIGHBORS {
+                    let (nx, ny) = (cx + dx, cy + dy);
+                    if let Some(i) = grid.get(nx, ny) {
+                        if new_labels[i] == usize::MAX && labels[i] == labels[start] {
+                            new_labels[i] = next_label;
+                            component.push(i);
+                            queue.push_back((nx, ny));
+                        }
+                    }
+                }
+            }
+
+            match adjacent {
+                Some(label) if component.len() < min_size => {
+                    component.iter().for_each(|&i| new_labels[i] = label);
+                }
+                _ => next_label += 1,
+            }
+        }
+    }
+
+    new_labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(
+        width: u32,
+        height: u32,
+        color: impl Fn(u32, u32) -> u32,
+    ) -> (Vec<Point>, Vec<(u32, u32)>) {
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| (Point::from([color(x, y); 3]), (x, y)))
+            .unzip()
+    }
+
+    #[test]
+    fn test_superpixels_follow_edges() {
+        let (points, coordinates) = image(20, 20, |x, _| if x < 10 { 0 } else { 255 });
+        let slic = Slic {
+            superpixels: 4,
+            compactness: 10.0,
+            iterations: 10,
+        };
+
+        let labels = slic.segment(&points, &coordinates).unwrap();
+
+        for ((&(x, _), &label), (&(other_x, _), &other_label)) in coordinates
+            .iter()
+            .zip(&labels)
+            .flat_map(|a| coordinates.iter().zip(&labels).map(move |b| (a, b)))
+        {
+            if label == other_label {
+                assert_eq!(x < 10, other_x < 10);
+            }
+        }
+    }
+
+    #[test]
+    fn test_superpixels_are_connected() {
+        let (points, coordinates) = image(30, 30, |x, y| (x * 7 + y * 13) % 256);
+        let slic = Slic {
+            superpixels: 9,
+            compactness: 1.0,
+            iterations: 10,
+        };
+
+        let labels = slic.segment(&points, &coordinates).unwrap();
+        let grid = Grid::new(&coordinates).unwrap();
+
+        // Relabeling connected components again must not split any superpixel
+        let relabeled = enforce_connectivity(&grid, &labels, 0);
+        let count = |labels: &[usize]| {
+            labels
+                .iter()
+                .collect::<std::collections::HashSet<_>>()
+                .len()
+        };
+        assert_eq!(count(&labels), count(&relabeled));
+    }
+
+    #[test]
+    fn test_sparse_pixels() {
+        let slic = Slic {
+            superpixels: 2,
+            compactness: 10.0,
+            iterations: 10,
+        };
+        let points = [Point::from([0; 3]), Point::from([255; 3])];
+
+        let labels = slic.segment(&points, &[(0, 0), (100, 100)]).unwrap();
+        assert_eq!(labels.len(), 2);
+
+        let far = [(0, 0), (4_000_000_000, 4_000_000_000)];
+        assert!(matches!(
+            slic.segment(&points, &far),
+            Err(InputError::SparsePixels { .. })
+        ));
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::HashSet;
+
+use crate::entities::Point;
+use crate::rows::InputError;
+
+/// Pixel position encoded in an `X:Y` label
+pub fn parse_coordinates(label: &str) -> Option<(u32, u32)> {
+    let (x, y) = label.split_once(':')?;
+    Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+}
+
+/// Pixel position of every point, each position being taken by a single point
+pub fn pixel_coordinates(points: &[Point]) -> Result<Vec<(u32, u32)>, InputError> {
+    let mut seen = HashSet::with_capacity(points.len());
+    points
+        .iter()
+        .map(|point| {
+            let label = point.get_label().unwrap_or_default();
+            let coordinates =
+                parse_coordinates(label).ok_or_else(|| InputError::NotAPixel(label.to_string()))?;
+            match seen.insert(coordinates) {
+                true => Ok(coordinates),
+                false => Err(InputError::DuplicatePixel(label.to_string())),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_coordinates() {
+        assert_eq!(parse_coordinates("12:7"), Some((12, 7)));
+        assert_eq!(parse_coordinates("12"), None);
+        assert_eq!(parse_coordinates("a:7"), None);
+    }
+
+    #[test]
+    fn test_pixel_coordinates() {
+        let pixel = |label: &str| Point::from([0, 0, 0]).with_label(label);
+        assert_eq!(
+            pixel_coordinates(&[pixel("0:0"), pixel("1:0")]).unwrap(),
+            vec![(0, 0), (1, 0)]
+        );
+
+        let error = pixel_coordinates(&[pixel("0:0"), pixel("1:0"), pixel("0:0")]).unwrap_err();
+        assert_eq!(error.to_string(), "pixel `0:0` appears more than once");
+        let error = pixel_coordinates(&[pixel("0:0"), pixel("x")]).unwrap_err();
+        assert_eq!(error.to_string(), "label `x` is not in the X:Y format");
+    }
+}
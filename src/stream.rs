@@ -0,0 +1,300 @@
+use std::cell::Cell;
+use std::error::Error;
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+
+use itertools::Itertools;
+use rand::Rng;
+use rayon::prelude::*;
+
+use crate::entities::Point;
+use crate::kmeans::common;
+use crate::model::Preprocessing;
+use crate::rows::{self, InputError, Layout, OnError, RowErrors};
+
+/// Points that can be read several times, one chunk at a time, without holding all of them
+pub trait Chunks {
+    fn for_each_chunk(
+        &self,
+        f: &mut dyn FnMut(Vec<Point>) -> Result<ControlFlow<()>, Box<dyn Error>>,
+    ) -> Result<(), Box<dyn Error>>;
+}
+
+/// CSV file, parsed again at every pass
+pub struct CsvChunks {
+    pub path: PathBuf,
+    pub chunk_size: usize,
+    pub layout: Layout,
+    pub preprocessing: Preprocessing,
+    pub on_error: OnError,
+    /// Rows skipped by the last complete pass
+    pub skipped: Cell<Option<usize>>,
+    /// Last line read by any pass, invalid rows are only warned about once
+    pub read_until: Cell<u64>,
+}
+
+impl Chunks for CsvChunks {
+    fn for_each_chunk(
+        &self,
+        f: &mut dyn FnMut(Vec<Point>) -> Result<ControlFlow<()>, Box<dyn Error>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut reader = self.layout.reader_builder().from_path(&self.path)?;
+        let headers = match self.layout.has_headers {
+            true => Some(reader.headers()?.clone()),
+            false => None,
+        };
+        let columns = self.layout.resolve(headers.as_ref())?;
+
+        let mut errors = RowErrors::new(self.on_error).quiet_until(self.read_until.get());
+        let mut rows = 0;
+
+        let records = rows::records(&mut reader);
+        for chunk in &records.chunks(self.chunk_size.max(1)) {
+            let mut points = Vec::with_capacity(self.chunk_size);
+            for record in chunk {
+                let line = match &record {
+                    Ok(record) => record.position().map(|position| position.line()),
+                    Err(error) => error.line(),
+                };
+                self.read_until
+                    .set(self.read_until.get().max(line.unwrap_or(0)));
+                let row = record.and_then(|record| columns.parse_record(&record));
+                if let Some((label, _, values, weight)) = errors.handle(row)? {
+                    let values = self.preprocessing.features(&label, &values)?;
+                    points.push(
+                        Point::from_vec(values)
+                            .with_label(&label)
+                            .with_weight(weight),
+                    );
+                }
+            }
+            rows += points.len();
+            if f(points)?.is_break() {
+                return Ok(());
+            }
+        }
+
+        if rows == 0 {
+            return Err(InputError::Empty.into());
+        }
+        self.skipped.set(Some(errors.skipped()));
+        Ok(())
+    }
+}
+
+/// Centers found by [`fit`] and the number of points assigned to each one
+pub struct Fitted {
+    pub centers: Vec<Point>,
+    pub counts: Vec<usize>,
+    pub iterations: usize,
+}
+
+/// The first `k` distinct points, reading no further than needed
+pub fn first_distinct_points(source: &dyn Chunks, k: usize) -> Result<Vec<Point>, Box<dyn Error>> {
+    let mut centers: Vec<Point> = Vec::with_capacity(k);
+    source.for_each_chunk(&mut |chunk| {
+        for point in chunk {
+            if centers.len() == k {
+                break;
+            }
+            if !centers
+                .iter()
+                .any(|center| center.get_values() == point.get_values())
+            {
+                centers.push(point);
+            }
+        }
+        Ok(match centers.len() == k {
+            true => ControlFlow::Break(()),
+            false => ControlFlow::Continue(()),
+        })
+    })?;
+    Ok(centers)
+}
+
+/// `k` points drawn uniformly in one pass (reservoir sampling)
+pub fn sample_points(
+    source: &dyn Chunks,
+    k: usize,
+    rng: &mut impl Rng,
+) -> Result<Vec<Point>, Box<dyn Error>> {
+    let mut sample = Vec::with_capacity(k);
+    let mut seen = 0;
+    source.for_each_chunk(&mut |chunk| {
+        for point in chunk {
+            if sample.len() < k {
+                sample.push(point);
+            } else {
+                let index = rng.gen_range(0..=seen);
+                if index < k {
+                    sample[index] = point;
+                }
+            }
+            seen += 1;
+        }
+        Ok(ControlFlow::Continue(()))
+    })?;
+    Ok(sample)
+}
+
+/// Lloyd iterations with one pass over `source` each: every chunk is assigned in parallel
+/// and only its per-cluster sums are kept
+pub fn fit(source: &dyn Chunks, initial_centers: Vec<Point>) -> Result<Fitted, Box<dyn Error>> {
+    let mut centers = initial_centers;
+    let dimension = centers.first().map_or(0, Point::dimension);
+    let mut iterations = 0;
+
+    loop {
+        let mut total = Sums::new(centers.len(), dimension);
+        source.for_each_chunk(&mut |chunk| {
+            total.add(accumulate(&chunk, &centers, dimension));
+            Ok(ControlFlow::Continue(()))
+        })?;
+        iterations += 1;
+
+        // Empty clusters get a zero center, like `Cluster::calculate_center_point`
+        let new_centers: Vec<Point> = total
+            .sums
+            .into_iter()
+            .zip(&total.weights)
+            .map(|(sum, &weight)| {
+                let weight = if weight > 0.0 { weight } else { 1.0 };
+                Point::from_vec(sum.into_iter().map(|value| value / weight).collect())
+            })
+            .collect();
+        let counts = total.counts;
+
+        if common::converged(&new_centers, &centers) {
+            return Ok(Fitted {
+                centers,
+                counts,
+                iterations,
+            });
+        }
+        centers = new_centers;
+    }
+}
+
+/// Per-cluster weighted sums of coordinates, total weights and number of points
+struct Sums {
+    sums: Vec<Vec<f64>>,
+    weights: Vec<f64>,
+    counts: Vec<usize>,
+}
+
+impl Sums {
+    fn new(clusters: usize, dimension: usize) -> Self {
+        Self {
+            sums: vec![vec![0.0; dimension]; clusters],
+            weights: vec![0.0; clusters],
+            counts: vec![0; clusters],
+        }
+    }
+
+    fn add(&mut self, other: Sums) {
+        for (sum, other) in self.sums.iter_mut().zip(other.sums) {
+            sum.iter_mut().zip(other).for_each(|(a, b)| *a += b);
+        }
+        for (weight, other) in self.weights.iter_mut().zip(other.weights) {
+            *weight += other;
+        }
+        for (count, other) in self.counts.iter_mut().zip(other.counts) {
+            *count += other;
+        }
+    }
+}
+
+/// [`Sums`] of the points of `chunk`
+fn accumulate(chunk: &[Point], centers: &[Point], dimension: usize) -> Sums {
+    chunk
+        .par_iter()
+        .fold(
+            || Sums::new(centers.len(), dimension),
+            |mut sums, point| {
+                let index = common::get_closest_cluster_index_based_in_centroids(point, centers);
+                let weight = point.get_weight();
+                sums.sums[index]
+                    .iter_mut()
+                    .zip(point.get_data())
+                    .for_each(|(sum, value)| *sum += weight * value);
+                sums.weights[index] += weight;
+                sums.counts[index] += 1;
+                sums
+            },
+        )
+        .reduce(
+            || Sums::new(centers.len(), dimension),
+            |mut sums, other| {
+                sums.add(other);
+                sums
+            },
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::kmeans::{serial::KmeansSerialBuilder, Kmeans};
+
+    /// In-memory points served in chunks of `chunk_size`
+    struct VecChunks {
+        points: Vec<Point>,
+        chunk_size: usize,
+    }
+
+    impl Chunks for VecChunks {
+        fn for_each_chunk(
+            &self,
+            f: &mut dyn FnMut(Vec<Point>) -> Result<ControlFlow<()>, Box<dyn Error>>,
+        ) -> Result<(), Box<dyn Error>> {
+            for chunk in self.points.chunks(self.chunk_size) {
+                if f(chunk.to_vec())?.is_break() {
+                    break;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn source() -> VecChunks {
+        VecChunks {
+            points: (0..500)
+                .map(|n| Point::from([(n * 37) % 101, (n * 53) % 97, n % 7]))
+                .collect(),
+            chunk_size: 64,
+        }
+    }
+
+    #[test]
+    fn test_fit_matches_serial() {
+        let source = source();
+        let initial_centers = first_distinct_points(&source, 5).unwrap();
+        let fitted = fit(&source, initial_centers.clone()).unwrap();
+
+        let serial = KmeansSerialBuilder.run(&source.points, 5, initial_centers);
+
+        assert_eq!(fitted.iterations, serial.iterations);
+        for (cluster, (center, &count)) in serial
+            .clusters
+            .iter()
+            .zip(fitted.centers.iter().zip(&fitted.counts))
+        {
+            assert_eq!(cluster.points.len(), count);
+            assert!(cluster.center.euclidean_distance(center) < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_initial_points() {
+        let source = source();
+        let first = first_distinct_points(&source, 3).unwrap();
+        assert_eq!(first, source.points[..3].to_vec());
+
+        let sample = |seed| sample_points(&source, 4, &mut StdRng::seed_from_u64(seed)).unwrap();
+        assert_eq!(sample(1), sample(1));
+        assert_eq!(sample(1).len(), 4);
+    }
+}
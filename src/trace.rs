@@ -0,0 +1,88 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::ControlFlow;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::kmeans::{Iteration, Observer};
+
+/// One iteration in a `--trace-out` file
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TracedIteration {
+    pub iteration: usize,
+    pub centers: Vec<Vec<f64>>,
+    /// Total weight of the points of each center
+    pub weights: Vec<f64>,
+    pub inertia: f64,
+    pub max_shift: f64,
+    /// Since the start of the run
+    pub elapsed_ms: f64,
+}
+
+/// Every iteration of a run, saved as JSON
+#[derive(Serialize, Debug, Default)]
+pub struct Trace {
+    pub iterations: Vec<TracedIteration>,
+}
+
+impl Observer for Trace {
+    fn iteration(&mut self, iteration: &Iteration) -> ControlFlow<()> {
+        self.iterations.push(TracedIteration {
+            iteration: iteration.index,
+            centers: iteration
+                .centers
+                .iter()
+                .map(|center| center.get_data().to_vec())
+                .collect(),
+            weights: iteration.weights.clone(),
+            inertia: iteration.inertia,
+            max_shift: iteration.max_shift,
+            elapsed_ms: iteration.elapsed.as_secs_f64() * 1000.0,
+        });
+        ControlFlow::Continue(())
+    }
+}
+
+impl Trace {
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::Point;
+    use crate::kmeans::serial::KmeansSerialBuilder;
+    use crate::kmeans::Kmeans;
+
+    #[test]
+    fn test_every_iteration_is_traced() {
+        let data = vec![
+            Point::from([0, 0]),
+            Point::from([1, 0]),
+            Point::from([10, 0]),
+            Point::from([11, 0]),
+        ];
+        let initial_centers = vec![Point::from([0, 0]), Point::from([1, 0])];
+
+        let mut trace = Trace::default();
+        let execution =
+            KmeansSerialBuilder.run_observed(&data, 2, initial_centers, Some(&mut trace));
+
+        assert_eq!(trace.iterations.len(), execution.iterations);
+        let first = &trace.iterations[0];
+        assert_eq!(first.iteration, 1);
+        assert_eq!(first.weights, vec![1.0, 3.0]);
+        assert_eq!(first.inertia, 81.0 + 100.0);
+        assert_eq!(first.centers, vec![vec![0.0, 0.0], vec![22.0 / 3.0, 0.0]]);
+        let last = trace.iterations.last().unwrap();
+        assert_eq!(last.weights, vec![2.0, 2.0]);
+        assert_eq!(last.max_shift, 0.0);
+    }
+}